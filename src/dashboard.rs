@@ -0,0 +1,228 @@
+use std::io;
+use std::thread::{self, JoinHandle};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    DefaultTerminal,
+    Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    symbols::Marker,
+    text::{Line, Span},
+    widgets::{Block, Gauge, Paragraph, Wrap},
+    widgets::canvas::{Canvas, Circle, Line as CanvasLine},
+};
+use digital_melee_controller::{Action, GameCubeControllerSnapshot, Technique};
+use keyboard_melee_controller::{KeyboardMeleeController, InputBackendHealth};
+
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(8);
+const DRAW_INTERVAL: Duration = Duration::from_millis(16);
+
+// Melee reads sticks in steps of 1/80.
+const MELEE_STICK_STEPS: f64 = 80.0;
+
+#[derive(Clone)]
+struct DashboardFrame {
+    output: GameCubeControllerSnapshot,
+    held_actions: Vec<Action>,
+    active_techniques: Vec<Technique>,
+    // Techniques stay listed for a moment so single-tick changes can be seen.
+    recent_techniques: Vec<Technique>,
+    is_paused: bool,
+    input_backend_health: InputBackendHealth,
+}
+
+// Draws on its own thread so a slow terminal never holds up the update loop.
+pub struct Dashboard {
+    frame: Arc<Mutex<Option<DashboardFrame>>>,
+    time_of_last_publish: Instant,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Dashboard {
+    pub fn start() -> io::Result<Self> {
+        let terminal = ratatui::try_init()?;
+        // Printing would draw over the dashboard, so messages are shown on it instead.
+        keyboard_melee_controller::hold_messages();
+        let frame = Arc::new(Mutex::new(None));
+        let thread_frame = frame.clone();
+        let thread = thread::spawn(move || run(terminal, thread_frame));
+        Ok(Self {
+            frame,
+            time_of_last_publish: Instant::now(),
+            thread: Some(thread),
+        })
+    }
+
+    pub fn publish(&mut self, controller: &KeyboardMeleeController) {
+        let now = Instant::now();
+        if now - self.time_of_last_publish < PUBLISH_INTERVAL {
+            return;
+        }
+
+        // Never wait on the drawing thread, the next tick will try again.
+        if let Ok(mut frame) = self.frame.try_lock() {
+            self.time_of_last_publish = now;
+            *frame = Some(DashboardFrame {
+                output: controller.controller.controller_state.snapshot(),
+                held_actions: controller.controller.held_actions(),
+                active_techniques: controller.controller.active_techniques().to_vec(),
+                recent_techniques: controller.controller.recent_techniques(),
+                is_paused: controller.is_paused(),
+                input_backend_health: controller.input_backend_health(),
+            });
+        }
+    }
+
+}
+
+// Stops the whole program, since the terminal can't be handed back while it keeps running.
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            keyboard_melee_controller::request_shutdown();
+            let _ = thread.join();
+            keyboard_melee_controller::release_messages();
+        }
+    }
+}
+
+// Key presses only reach the terminal while it has focus and the keys aren't blocked.
+fn run(mut terminal: DefaultTerminal, frame: Arc<Mutex<Option<DashboardFrame>>>) {
+    let mut messages = Vec::new();
+    while !keyboard_melee_controller::shutdown_requested() {
+        messages.extend(keyboard_melee_controller::take_held_messages());
+        let dashboard_frame = frame.lock().unwrap().clone();
+        if let Some(dashboard_frame) = dashboard_frame {
+            let last_message = messages.last().map(String::as_str);
+            if terminal.draw(|frame| draw(frame, &dashboard_frame, last_message)).is_err() {
+                break;
+            }
+        }
+
+        if event::poll(DRAW_INTERVAL).unwrap_or(false) {
+            if let Ok(Event::Key(key)) = event::read() {
+                let is_quit = key.kind == KeyEventKind::Press
+                    && (key.code == KeyCode::Char('q')
+                     || key.code == KeyCode::Esc
+                     || key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL));
+                if is_quit {
+                    keyboard_melee_controller::request_shutdown();
+                }
+            }
+        }
+    }
+    ratatui::restore();
+
+    // Everything shown while running is printed again so it isn't lost with the dashboard.
+    for message in messages {
+        println!("{}", message);
+    }
+}
+
+fn draw(frame: &mut Frame, dashboard_frame: &DashboardFrame, last_message: Option<&str>) {
+    let output = &dashboard_frame.output;
+    let [sticks_area, buttons_area, triggers_area, actions_area, techniques_area, message_area, status_area] = Layout::vertical([
+        Constraint::Min(12),
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Length(4),
+        Constraint::Length(3),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+
+    let [main_stick_area, c_stick_area] = Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).areas(sticks_area);
+    draw_stick(frame, main_stick_area, "Main stick", output.x_axis, output.y_axis, Color::Gray);
+    draw_stick(frame, c_stick_area, "C-stick", output.c_x_axis, output.c_y_axis, Color::Yellow);
+
+    let buttons = [
+        ("A", output.a), ("B", output.b), ("X", output.x), ("Y", output.y),
+        ("Z", output.z), ("L", output.l), ("R", output.r), ("Start", output.start),
+        ("D-Left", output.d_left), ("D-Right", output.d_right), ("D-Down", output.d_down), ("D-Up", output.d_up),
+    ];
+    let button_spans: Vec<Span> = buttons.iter()
+        .flat_map(|(name, is_pressed)| {
+            let style = if *is_pressed {
+                Style::default().fg(Color::Black).bg(Color::Green).add_modifier(Modifier::BOLD)
+            }
+            else {
+                Style::default().fg(Color::DarkGray)
+            };
+            [Span::styled(format!(" {} ", name), style), Span::raw(" ")]
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(Line::from(button_spans)).block(Block::bordered().title("Buttons")), buttons_area);
+
+    let [l_area, r_area] = Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).areas(triggers_area);
+    draw_trigger(frame, l_area, "L", output.l_analog, output.l);
+    draw_trigger(frame, r_area, "R", output.r_analog, output.r);
+
+    let held_actions = dashboard_frame.held_actions.iter()
+        .map(|action| format!("{:?}", action))
+        .collect::<Vec<String>>()
+        .join("  ");
+    frame.render_widget(
+        Paragraph::new(held_actions).wrap(Wrap { trim: true }).block(Block::bordered().title("Held actions")),
+        actions_area,
+    );
+
+    let technique_spans: Vec<Span> = dashboard_frame.recent_techniques.iter()
+        .map(|technique| {
+            let style = if dashboard_frame.active_techniques.contains(technique) {
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+            }
+            else {
+                Style::default().fg(Color::DarkGray)
+            };
+            Span::styled(format!("{:?}  ", technique), style)
+        })
+        .collect();
+    frame.render_widget(
+        Paragraph::new(Line::from(technique_spans)).block(Block::bordered().title("Techniques changing the output")),
+        techniques_area,
+    );
+
+    let status = match (dashboard_frame.is_paused, dashboard_frame.input_backend_health) {
+        (_, InputBackendHealth::Disconnected) => Span::styled("No keyboard input", Style::default().fg(Color::Red)),
+        (true, _) => Span::styled("Paused", Style::default().fg(Color::Yellow)),
+        (false, _) => Span::styled("Running", Style::default().fg(Color::Green)),
+    };
+    frame.render_widget(Paragraph::new(last_message.unwrap_or("")).style(Style::default().fg(Color::Gray)), message_area);
+    frame.render_widget(Paragraph::new(Line::from(vec![status, Span::raw("   q to quit")])), status_area);
+}
+
+fn melee_coordinate(value: f64) -> f64 {
+    (value * MELEE_STICK_STEPS).round() / MELEE_STICK_STEPS
+}
+
+fn draw_stick(frame: &mut Frame, area: Rect, name: &str, x: f64, y: f64, color: Color) {
+    let x = melee_coordinate(x);
+    let y = melee_coordinate(y);
+    let canvas = Canvas::default()
+        .block(Block::bordered().title(format!("{}  {:>7.4}, {:>7.4}", name, x, y)))
+        .marker(Marker::Braille)
+        .x_bounds([-1.1, 1.1])
+        .y_bounds([-1.1, 1.1])
+        .paint(move |context| {
+            context.draw(&Circle { x: 0.0, y: 0.0, radius: 1.0, color: Color::DarkGray });
+            context.draw(&CanvasLine { x1: -1.0, y1: 0.0, x2: 1.0, y2: 0.0, color: Color::DarkGray });
+            context.draw(&CanvasLine { x1: 0.0, y1: -1.0, x2: 0.0, y2: 1.0, color: Color::DarkGray });
+            context.layer();
+            context.draw(&CanvasLine { x1: 0.0, y1: 0.0, x2: x, y2: y, color });
+            context.draw(&Circle { x, y, radius: 0.06, color });
+        });
+    frame.render_widget(canvas, area);
+}
+
+fn draw_trigger(frame: &mut Frame, area: Rect, name: &str, value: f64, is_pressed: bool) {
+    let color = if is_pressed { Color::Green } else { Color::Blue };
+    let gauge = Gauge::default()
+        .block(Block::bordered().title(if is_pressed { format!("{} (digital)", name) } else { name.to_string() }))
+        .gauge_style(Style::default().fg(color))
+        .ratio(value.clamp(0.0, 1.0))
+        .label(format!("{:.3}", value));
+    frame.render_widget(gauge, area);
+}
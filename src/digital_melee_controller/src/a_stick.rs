@@ -1,8 +1,46 @@
 use std::time::{Instant, Duration};
 
+use serde::{Serialize, Deserialize};
+
 use crate::delayed_button::DelayedButton;
 use crate::analog_axis::AnalogAxis;
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AStickSettings {
+    pub neutral_min_hold_ms: u64,
+    pub side_min_hold_ms: u64,
+    pub vertical_min_hold_ms: u64,
+    pub neutral_delay_ms: u64,
+    pub side_delay_ms: u64,
+    pub turn_around_delay_ms: u64,
+    pub vertical_delay_ms: u64,
+    pub neutral_axis_hold_ms: u64,
+    pub axis_hold_ms: u64,
+    pub axis_level: f64,
+    pub x_bias: f64,
+    pub y_bias: f64,
+}
+
+impl Default for AStickSettings {
+    fn default() -> Self {
+        Self {
+            neutral_min_hold_ms: 34,
+            side_min_hold_ms: 50,
+            vertical_min_hold_ms: 34,
+            neutral_delay_ms: 0,
+            side_delay_ms: 17,
+            turn_around_delay_ms: 34,
+            vertical_delay_ms: 0,
+            neutral_axis_hold_ms: 25,
+            axis_hold_ms: 67,
+            axis_level: 0.6,
+            x_bias: 0.35,
+            y_bias: 0.5,
+        }
+    }
+}
+
 pub struct AStick {
     output_state: bool,
     x_axis_output: f64,
@@ -17,9 +55,32 @@ pub struct AStick {
     up_button: DelayedButton,
     axis_hold_duration: Duration,
     activation_time: Instant,
+    settings: AStickSettings,
 }
 
 impl AStick {
+    pub fn new(settings: &AStickSettings) -> Self {
+        let neutral_min_hold = Duration::from_millis(settings.neutral_min_hold_ms);
+        let side_min_hold = Duration::from_millis(settings.side_min_hold_ms);
+        let vertical_min_hold = Duration::from_millis(settings.vertical_min_hold_ms);
+        Self {
+            output_state: false,
+            x_axis_output: 0.0,
+            y_axis_output: 0.0,
+            output_x_axis: Default::default(),
+            output_y_axis: Default::default(),
+            output_button: Default::default(),
+            neutral_button: DelayedButton::new(Duration::from_millis(0), neutral_min_hold),
+            left_button: DelayedButton::new(Duration::from_millis(0), side_min_hold),
+            right_button: DelayedButton::new(Duration::from_millis(0), side_min_hold),
+            down_button: DelayedButton::new(Duration::from_millis(0), vertical_min_hold),
+            up_button: DelayedButton::new(Duration::from_millis(0), vertical_min_hold),
+            axis_hold_duration: Duration::from_millis(0),
+            activation_time: Instant::now(),
+            settings: *settings,
+        }
+    }
+
     pub fn output_state(&self) -> bool { self.output_state }
     pub fn x_axis_output(&self) -> f64 { self.x_axis_output }
     pub fn y_axis_output(&self) -> f64 { self.y_axis_output }
@@ -55,25 +116,25 @@ impl AStick {
 
         if turn_around_left_tilt || turn_around_right_tilt {
             self.activation_time = Instant::now();
-            self.output_button.set_delay(Duration::from_millis(34));
-            self.axis_hold_duration = Duration::from_millis(67);
+            self.output_button.set_delay(Duration::from_millis(self.settings.turn_around_delay_ms));
+            self.axis_hold_duration = Duration::from_millis(self.settings.axis_hold_ms);
         }
         else if self.left_button.just_pressed() || self.right_button.just_pressed() {
             self.activation_time = Instant::now();
-            self.output_button.set_delay(Duration::from_millis(17));
-            self.axis_hold_duration = Duration::from_millis(67);
+            self.output_button.set_delay(Duration::from_millis(self.settings.side_delay_ms));
+            self.axis_hold_duration = Duration::from_millis(self.settings.axis_hold_ms);
         }
 
         if self.down_button.just_pressed() || self.up_button.just_pressed() {
             self.activation_time = Instant::now();
-            self.output_button.set_delay(Duration::from_millis(0));
-            self.axis_hold_duration = Duration::from_millis(67);
+            self.output_button.set_delay(Duration::from_millis(self.settings.vertical_delay_ms));
+            self.axis_hold_duration = Duration::from_millis(self.settings.axis_hold_ms);
         }
 
         if self.neutral_button.just_pressed() {
             self.activation_time = Instant::now();
-            self.output_button.set_delay(Duration::from_millis(0));
-            self.axis_hold_duration = Duration::from_millis(25);
+            self.output_button.set_delay(Duration::from_millis(self.settings.neutral_delay_ms));
+            self.axis_hold_duration = Duration::from_millis(self.settings.neutral_axis_hold_ms);
         }
 
         self.output_button.set_state(self.neutral_button.is_pressed()
@@ -97,9 +158,9 @@ impl AStick {
                                   || self.neutral_button.is_pressed());
             let mut x_bias = 0.0;
             if should_bias_x {
-                x_bias = 0.35 * x_axis.direction();
+                x_bias = self.settings.x_bias * x_axis.direction();
             }
-            self.x_axis_output = self.output_x_axis.value() * 0.6 + x_bias;
+            self.x_axis_output = self.output_x_axis.value() * self.settings.axis_level + x_bias;
 
             let should_bias_y = y_axis.is_active()
                                 && !(self.down_button.is_pressed()
@@ -107,9 +168,9 @@ impl AStick {
                                      || self.neutral_button.is_pressed());
             let mut y_bias = 0.0;
             if should_bias_y {
-                y_bias = 0.5 * y_axis.direction();
+                y_bias = self.settings.y_bias * y_axis.direction();
             }
-            self.y_axis_output = self.output_y_axis.value() * 0.6 + y_bias;
+            self.y_axis_output = self.output_y_axis.value() * self.settings.axis_level + y_bias;
         }
 
         self.output_state = self.output_button.is_pressed();
@@ -118,20 +179,6 @@ impl AStick {
 
 impl Default for AStick {
     fn default() -> Self {
-        Self {
-            output_state: false,
-            x_axis_output: 0.0,
-            y_axis_output: 0.0,
-            output_x_axis: Default::default(),
-            output_y_axis: Default::default(),
-            output_button: Default::default(),
-            neutral_button: DelayedButton::new(Duration::from_millis(0), Duration::from_millis(34)),
-            left_button: DelayedButton::new(Duration::from_millis(0), Duration::from_millis(50)),
-            right_button: DelayedButton::new(Duration::from_millis(0), Duration::from_millis(50)),
-            down_button: DelayedButton::new(Duration::from_millis(0), Duration::from_millis(34)),
-            up_button: DelayedButton::new(Duration::from_millis(0), Duration::from_millis(34)),
-            axis_hold_duration: Duration::from_millis(0),
-            activation_time: Instant::now(),
-        }
+        Self::new(&AStickSettings::default())
     }
 }
@@ -1,8 +1,38 @@
 use std::time::{Instant, Duration};
 
+use serde::{Serialize, Deserialize};
+
 use crate::button::Button;
 use crate::analog_axis::AnalogAxis;
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AirDodgeSettings {
+    pub air_dodge_duration_ms: u64,
+    pub neutral_y_level: f64,
+    pub long_x: f64,
+    pub long_y: f64,
+    pub medium_x: f64,
+    pub medium_y: f64,
+    pub short_x: f64,
+    pub short_y: f64,
+}
+
+impl Default for AirDodgeSettings {
+    fn default() -> Self {
+        Self {
+            air_dodge_duration_ms: 51,
+            neutral_y_level: -0.3,
+            long_x: 0.925,
+            long_y: -0.35,
+            medium_x: 0.8125,
+            medium_y: -0.575,
+            short_x: 0.5,
+            short_y: -0.85,
+        }
+    }
+}
+
 pub struct AirDodgeLogic {
     air_dodge_input: Button,
     air_dodge_time: Instant,
@@ -13,9 +43,27 @@ pub struct AirDodgeLogic {
     y_level_medium: f64,
     x_level_short: f64,
     y_level_short: f64,
+    neutral_y_level: f64,
+    air_dodge_duration: Duration,
 }
 
 impl AirDodgeLogic {
+    pub fn new(settings: &AirDodgeSettings) -> Self {
+        Self {
+            x_level_long: settings.long_x,
+            y_level_long: settings.long_y,
+            x_level_medium: settings.medium_x,
+            y_level_medium: settings.medium_y,
+            x_level_short: settings.short_x,
+            y_level_short: settings.short_y,
+            neutral_y_level: settings.neutral_y_level,
+            air_dodge_duration: Duration::from_millis(settings.air_dodge_duration_ms),
+            air_dodge_input: Default::default(),
+            air_dodge_time: Instant::now(),
+            is_air_dodging: false,
+        }
+    }
+
     pub fn update_axes(&mut self,
         x_axis: &mut AnalogAxis,
         y_axis: &mut AnalogAxis,
@@ -42,7 +90,7 @@ impl AirDodgeLogic {
         }
 
         if self.is_air_dodging && !is_up {
-            if Instant::now() - self.air_dodge_time < Duration::from_millis(51) {
+            if Instant::now() - self.air_dodge_time < self.air_dodge_duration {
                 if air_dodge_long {
                     x_axis.set_value(x_axis.direction() * self.x_level_long);
                     y_axis.set_value(self.y_level_long);
@@ -56,7 +104,7 @@ impl AirDodgeLogic {
                     y_axis.set_value(self.y_level_short);
                 }
                 else if !is_down {
-                    y_axis.set_value(-0.3);
+                    y_axis.set_value(self.neutral_y_level);
                 }
             }
             else {
@@ -68,16 +116,6 @@ impl AirDodgeLogic {
 
 impl Default for AirDodgeLogic {
     fn default() -> Self {
-        Self {
-            air_dodge_input: Default::default(),
-            air_dodge_time: Instant::now(),
-            is_air_dodging: false,
-            x_level_long: 0.925,
-            y_level_long: -0.35,
-            x_level_medium: 0.8125,
-            y_level_medium: -0.575,
-            x_level_short: 0.5,
-            y_level_short: -0.85,
-        }
+        Self::new(&AirDodgeSettings::default())
     }
 }
@@ -1,8 +1,42 @@
 use std::time::{Instant, Duration};
 
+use serde::{Serialize, Deserialize};
+
 use crate::delayed_button::DelayedButton;
 use crate::analog_axis::AnalogAxis;
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BStickSettings {
+    pub neutral_min_hold_ms: u64,
+    pub side_min_hold_ms: u64,
+    pub down_min_hold_ms: u64,
+    pub up_min_hold_ms: u64,
+    pub up_delay_ms: u64,
+    pub up_delay_y_threshold: f64,
+    pub neutral_axis_hold_ms: u64,
+    pub axis_hold_ms: u64,
+    pub axis_level: f64,
+    pub x_bias: f64,
+}
+
+impl Default for BStickSettings {
+    fn default() -> Self {
+        Self {
+            neutral_min_hold_ms: 34,
+            side_min_hold_ms: 34,
+            down_min_hold_ms: 34,
+            up_min_hold_ms: 50,
+            up_delay_ms: 17,
+            up_delay_y_threshold: 0.6,
+            neutral_axis_hold_ms: 25,
+            axis_hold_ms: 50,
+            axis_level: 0.6,
+            x_bias: 0.5,
+        }
+    }
+}
+
 pub struct BStick {
     output_state: bool,
     x_axis_output: f64,
@@ -17,9 +51,33 @@ pub struct BStick {
     up_button: DelayedButton,
     axis_hold_duration: Duration,
     activation_time: Instant,
+    settings: BStickSettings,
 }
 
 impl BStick {
+    pub fn new(settings: &BStickSettings) -> Self {
+        let neutral_min_hold = Duration::from_millis(settings.neutral_min_hold_ms);
+        let side_min_hold = Duration::from_millis(settings.side_min_hold_ms);
+        let down_min_hold = Duration::from_millis(settings.down_min_hold_ms);
+        let up_min_hold = Duration::from_millis(settings.up_min_hold_ms);
+        Self {
+            output_state: false,
+            x_axis_output: 0.0,
+            y_axis_output: 0.0,
+            output_x_axis: Default::default(),
+            output_y_axis: Default::default(),
+            output_button: Default::default(),
+            neutral_button: DelayedButton::new(Duration::from_millis(0), neutral_min_hold),
+            left_button: DelayedButton::new(Duration::from_millis(0), side_min_hold),
+            right_button: DelayedButton::new(Duration::from_millis(0), side_min_hold),
+            down_button: DelayedButton::new(Duration::from_millis(0), down_min_hold),
+            up_button: DelayedButton::new(Duration::from_millis(0), up_min_hold),
+            axis_hold_duration: Duration::from_millis(0),
+            activation_time: Instant::now(),
+            settings: *settings,
+        }
+    }
+
     pub fn output_state(&self) -> bool { self.output_state }
     pub fn x_axis_output(&self) -> f64 { self.x_axis_output }
     pub fn y_axis_output(&self) -> f64 { self.y_axis_output }
@@ -53,13 +111,13 @@ impl BStick {
 
         if self.up_button.just_pressed() {
             self.activation_time = Instant::now();
-            if y_axis.value() <= 0.6 || shield {
-                self.output_button.set_delay(Duration::from_millis(17));
+            if y_axis.value() <= self.settings.up_delay_y_threshold || shield {
+                self.output_button.set_delay(Duration::from_millis(self.settings.up_delay_ms));
             }
             else {
                 self.output_button.set_delay(Duration::from_millis(0));
             }
-            self.axis_hold_duration = Duration::from_millis(50);
+            self.axis_hold_duration = Duration::from_millis(self.settings.axis_hold_ms);
         }
 
         if self.down_button.just_pressed()
@@ -67,13 +125,13 @@ impl BStick {
         || self.right_button.just_pressed() {
             self.activation_time = Instant::now();
             self.output_button.set_delay(Duration::from_millis(0));
-            self.axis_hold_duration = Duration::from_millis(50);
+            self.axis_hold_duration = Duration::from_millis(self.settings.axis_hold_ms);
         }
 
         if self.neutral_button.just_pressed() {
             self.activation_time = Instant::now();
             self.output_button.set_delay(Duration::from_millis(0));
-            self.axis_hold_duration = Duration::from_millis(25);
+            self.axis_hold_duration = Duration::from_millis(self.settings.neutral_axis_hold_ms);
         }
 
         self.output_button.set_state(self.neutral_button.is_pressed()
@@ -97,12 +155,12 @@ impl BStick {
                                  || (x_axis.is_active() && self.neutral_button.is_pressed());
             let mut x_bias = 0.0;
             if should_bias_x {
-                x_bias = 0.5 * x_axis.direction();
+                x_bias = self.settings.x_bias * x_axis.direction();
             }
-            self.x_axis_output = self.output_x_axis.value() * 0.6 + x_bias;
+            self.x_axis_output = self.output_x_axis.value() * self.settings.axis_level + x_bias;
 
             if self.output_y_axis.value() < 0.0 {
-                self.y_axis_output = self.output_y_axis.value() * 0.6;
+                self.y_axis_output = self.output_y_axis.value() * self.settings.axis_level;
             }
             else {
                 self.y_axis_output = self.output_y_axis.value();
@@ -115,20 +173,6 @@ impl BStick {
 
 impl Default for BStick {
     fn default() -> Self {
-        Self {
-            output_state: false,
-            x_axis_output: 0.0,
-            y_axis_output: 0.0,
-            output_x_axis: Default::default(),
-            output_y_axis: Default::default(),
-            output_button: Default::default(),
-            neutral_button: DelayedButton::new(Duration::from_millis(0), Duration::from_millis(34)),
-            left_button: DelayedButton::new(Duration::from_millis(0), Duration::from_millis(34)),
-            right_button: DelayedButton::new(Duration::from_millis(0), Duration::from_millis(34)),
-            down_button: DelayedButton::new(Duration::from_millis(0), Duration::from_millis(34)),
-            up_button: DelayedButton::new(Duration::from_millis(0), Duration::from_millis(50)),
-            axis_hold_duration: Duration::from_millis(0),
-            activation_time: Instant::now(),
-        }
+        Self::new(&BStickSettings::default())
     }
 }
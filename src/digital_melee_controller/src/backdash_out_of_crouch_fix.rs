@@ -1,8 +1,24 @@
 use std::time::{Instant, Duration};
 
+use serde::{Serialize, Deserialize};
+
 use crate::button::Button;
 use crate::analog_axis::AnalogAxis;
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackdashOutOfCrouchFixSettings {
+    pub backdash_fix_duration_ms: u64,
+}
+
+impl Default for BackdashOutOfCrouchFixSettings {
+    fn default() -> Self {
+        Self {
+            backdash_fix_duration_ms: 50,
+        }
+    }
+}
+
 pub struct BackdashOutOfCrouchFix {
     x_axis_output: f64,
     down_input: Button,
@@ -14,6 +30,18 @@ pub struct BackdashOutOfCrouchFix {
 }
 
 impl BackdashOutOfCrouchFix {
+    pub fn new(settings: &BackdashOutOfCrouchFixSettings) -> Self {
+        Self {
+            backdash_fix_duration: Duration::from_millis(settings.backdash_fix_duration_ms),
+            x_axis_output: 0.0,
+            down_input: Default::default(),
+            left_input: Default::default(),
+            right_input: Default::default(),
+            delay_backdash: false,
+            backdash_time: Instant::now(),
+        }
+    }
+
     pub fn x_axis_output(&self) -> f64 { self.x_axis_output }
 
     pub fn update_state(
@@ -22,6 +50,7 @@ impl BackdashOutOfCrouchFix {
         left: bool,
         right: bool,
         down: bool,
+        is_grounded: bool,
     ) {
         self.x_axis_output = x_axis.value();
 
@@ -33,8 +62,9 @@ impl BackdashOutOfCrouchFix {
         self.left_input.set_state(left);
         self.right_input.set_state(right);
 
-        if self.down_input.is_pressed() && (self.left_input.just_pressed()
-                                         || self.right_input.just_pressed()) {
+        // There is no crouch to backdash out of in the air.
+        if is_grounded && self.down_input.is_pressed() && (self.left_input.just_pressed()
+                                                        || self.right_input.just_pressed()) {
             self.delay_backdash = true;
             self.backdash_time = Instant::now();
         }
@@ -54,14 +84,6 @@ impl BackdashOutOfCrouchFix {
 
 impl Default for BackdashOutOfCrouchFix {
     fn default() -> Self {
-        Self {
-            x_axis_output: 0.0,
-            down_input: Default::default(),
-            left_input: Default::default(),
-            right_input: Default::default(),
-            delay_backdash: false,
-            backdash_time: Instant::now(),
-            backdash_fix_duration: Duration::from_millis(50)
-        }
+        Self::new(&BackdashOutOfCrouchFixSettings::default())
     }
 }
@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::technique_settings::TechniqueSettings;
+
+pub fn frames_to_duration(frames: f64) -> Duration {
+    Duration::from_millis((frames * 1000.0 / 60.0).ceil() as u64)
+}
+
+// Jumpsquat is known for every character. The rest are None unless a character
+// needs something other than the shared value in TechniqueSettings.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CharacterPreset {
+    pub jumpsquat_frames: f64,
+    pub short_hop_release_frame: Option<f64>,
+    pub air_dodge_long_x: Option<f64>,
+    pub air_dodge_long_y: Option<f64>,
+    pub air_dodge_medium_x: Option<f64>,
+    pub air_dodge_medium_y: Option<f64>,
+    pub air_dodge_short_x: Option<f64>,
+    pub air_dodge_short_y: Option<f64>,
+    pub tilt_level: Option<f64>,
+    pub shield_tilt_level: Option<f64>,
+}
+
+impl CharacterPreset {
+    pub fn full_hop_duration(&self) -> Duration {
+        frames_to_duration(self.jumpsquat_frames)
+    }
+
+    pub fn full_hop_release_duration(&self) -> Duration {
+        frames_to_duration(self.jumpsquat_frames + 1.0)
+    }
+
+    pub fn with_overrides(mut self, overrides: &CharacterPresetOverrides) -> Self {
+        if let Some(value) = overrides.jumpsquat_frames { self.jumpsquat_frames = value; }
+        self.short_hop_release_frame = overrides.short_hop_release_frame.or(self.short_hop_release_frame);
+        self.air_dodge_long_x = overrides.air_dodge_long_x.or(self.air_dodge_long_x);
+        self.air_dodge_long_y = overrides.air_dodge_long_y.or(self.air_dodge_long_y);
+        self.air_dodge_medium_x = overrides.air_dodge_medium_x.or(self.air_dodge_medium_x);
+        self.air_dodge_medium_y = overrides.air_dodge_medium_y.or(self.air_dodge_medium_y);
+        self.air_dodge_short_x = overrides.air_dodge_short_x.or(self.air_dodge_short_x);
+        self.air_dodge_short_y = overrides.air_dodge_short_y.or(self.air_dodge_short_y);
+        self.tilt_level = overrides.tilt_level.or(self.tilt_level);
+        self.shield_tilt_level = overrides.shield_tilt_level.or(self.shield_tilt_level);
+        self
+    }
+
+    // The shared technique settings with this character's values in their place.
+    pub fn apply_to(&self, settings: &TechniqueSettings) -> TechniqueSettings {
+        let mut settings = *settings;
+        if let Some(value) = self.short_hop_release_frame { settings.short_hop_release_frame = value; }
+        if let Some(value) = self.air_dodge_long_x { settings.air_dodge.long_x = value; }
+        if let Some(value) = self.air_dodge_long_y { settings.air_dodge.long_y = value; }
+        if let Some(value) = self.air_dodge_medium_x { settings.air_dodge.medium_x = value; }
+        if let Some(value) = self.air_dodge_medium_y { settings.air_dodge.medium_y = value; }
+        if let Some(value) = self.air_dodge_short_x { settings.air_dodge.short_x = value; }
+        if let Some(value) = self.air_dodge_short_y { settings.air_dodge.short_y = value; }
+        if let Some(value) = self.tilt_level { settings.tilt_level = value; }
+        if let Some(value) = self.shield_tilt_level { settings.shield_tilt_level = value; }
+        settings
+    }
+}
+
+impl Default for CharacterPreset {
+    fn default() -> Self {
+        Self {
+            // Long enough to full hop with every character.
+            jumpsquat_frames: 8.0,
+            short_hop_release_frame: None,
+            air_dodge_long_x: None,
+            air_dodge_long_y: None,
+            air_dodge_medium_x: None,
+            air_dodge_medium_y: None,
+            air_dodge_short_x: None,
+            air_dodge_short_y: None,
+            tilt_level: None,
+            shield_tilt_level: None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CharacterPresetOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jumpsquat_frames: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_hop_release_frame: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub air_dodge_long_x: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub air_dodge_long_y: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub air_dodge_medium_x: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub air_dodge_medium_y: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub air_dodge_short_x: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub air_dodge_short_y: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tilt_level: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shield_tilt_level: Option<f64>,
+}
+
+macro_rules! define_characters {
+    ($($character:ident: $jumpsquat_frames:expr),+) => {
+        #[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        pub enum Character {
+            #[default]
+            Generic,
+            $($character,)+
+        }
+
+        impl Character {
+            pub fn preset(self) -> CharacterPreset {
+                match self {
+                    Character::Generic => CharacterPreset::default(),
+                    $(Character::$character => CharacterPreset {
+                        jumpsquat_frames: $jumpsquat_frames,
+                        ..Default::default()
+                    },)+
+                }
+            }
+        }
+    };
+}
+
+define_characters!(
+    Bowser: 8.0,
+    CaptainFalcon: 4.0,
+    DonkeyKong: 5.0,
+    DrMario: 4.0,
+    Falco: 5.0,
+    Fox: 3.0,
+    GameAndWatch: 4.0,
+    Ganondorf: 6.0,
+    IceClimbers: 3.0,
+    Jigglypuff: 5.0,
+    Kirby: 3.0,
+    Link: 6.0,
+    Luigi: 4.0,
+    Mario: 4.0,
+    Marth: 4.0,
+    Mewtwo: 5.0,
+    Ness: 4.0,
+    Peach: 5.0,
+    Pichu: 3.0,
+    Pikachu: 3.0,
+    Roy: 5.0,
+    Samus: 3.0,
+    Sheik: 3.0,
+    Yoshi: 5.0,
+    YoungLink: 4.0,
+    Zelda: 6.0
+);
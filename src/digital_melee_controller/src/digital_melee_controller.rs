@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 use serde::{Serialize, Deserialize};
 
 use crate::button::Button;
@@ -9,6 +11,11 @@ use crate::a_stick::AStick;
 use crate::b_stick::BStick;
 use crate::backdash_out_of_crouch_fix::BackdashOutOfCrouchFix;
 use crate::safe_grounded_down_b::SafeGroundedDownB;
+use crate::character_preset::{CharacterPreset, frames_to_duration};
+use crate::game_state::GameState;
+use crate::technique_settings::{TechniqueSettings, ShieldSlider};
+
+const RECENT_TECHNIQUE_HOLD_TIME: Duration = Duration::from_millis(250);
 
 macro_rules! define_actions {
     ($($variant:ident),+) => {
@@ -18,6 +25,10 @@ macro_rules! define_actions {
             $($variant,)+
         }
 
+        impl Action {
+            pub const ALL: &'static [Action] = &[$(Action::$variant,)+];
+        }
+
         #[allow(non_snake_case)]
         #[derive(Default)]
         pub struct ActionStates {
@@ -29,6 +40,14 @@ macro_rules! define_actions {
                 $(self.action_states.$variant.update_previous_state();)+
             }
 
+            pub fn action_is_pressed(&self, action: Action) -> bool {
+                self.action_button(action).is_pressed()
+            }
+
+            pub fn held_actions(&self) -> Vec<Action> {
+                Action::ALL.iter().copied().filter(|action| self.action_is_pressed(*action)).collect()
+            }
+
             fn action_button(&self, action: Action) -> &Button {
                 match action {
                     $(Action::$variant => &self.action_states.$variant,)+
@@ -65,6 +84,9 @@ define_actions!(
     Z,
     Shield,
     ToggleLightShield,
+    AnalogShield1,
+    AnalogShield2,
+    AnalogShield3,
     AirDodge,
     Start,
     DLeft,
@@ -75,6 +97,23 @@ define_actions!(
     InvertXAxis
 );
 
+// The steps of process_actions that can change the output on their own.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Technique {
+    XAxisInversion,
+    BackdashOutOfCrouchFix,
+    ModifierAngles,
+    AStick,
+    TiltModifier,
+    BStick,
+    ShieldTilt,
+    AirDodge,
+    AngledSmash,
+    ChargedSmash,
+    Jump,
+    Shield,
+}
+
 pub struct DigitalMeleeController {
     pub controller_state: GameCubeControllerState,
     action_states: ActionStates,
@@ -90,12 +129,13 @@ pub struct DigitalMeleeController {
     use_c_stick_tilting: bool,
     use_extra_b_buttons: bool,
     previous_direction_is_right: bool,
+    game_state: Option<GameState>,
     is_light_shielding: bool,
     charge_smash: bool,
-    x_mod_x: f64,
-    x_mod_y: f64,
-    y_mod_x: f64,
-    y_mod_y: f64,
+    character_preset: CharacterPreset,
+    technique_settings: TechniqueSettings,
+    active_techniques: Vec<Technique>,
+    recent_techniques: Vec<(Technique, Instant)>,
 }
 
 impl DigitalMeleeController {
@@ -103,25 +143,96 @@ impl DigitalMeleeController {
     pub fn set_use_c_stick_tilting(&mut self, value: bool) { self.use_c_stick_tilting = value; }
     pub fn set_use_extra_b_buttons(&mut self, value: bool) { self.use_extra_b_buttons = value; }
 
+    pub fn set_character_preset(&mut self, preset: &CharacterPreset) {
+        self.character_preset = *preset;
+        self.rebuild_techniques();
+    }
+
+    pub fn set_technique_settings(&mut self, settings: &TechniqueSettings) {
+        self.technique_settings = *settings;
+        self.rebuild_techniques();
+    }
+
+    fn rebuild_techniques(&mut self) {
+        let preset = &self.character_preset;
+        let settings = &preset.apply_to(&self.technique_settings);
+        self.jump_logic = JumpLogic::new(
+            frames_to_duration(settings.short_hop_release_frame),
+            preset.full_hop_duration(),
+            preset.full_hop_release_duration(),
+        );
+        self.tilt_modifier = StickTilter::new(settings.tilt_level, &settings.tilt);
+        self.shield_tilter = StickTilter::new(settings.shield_tilt_level, &settings.shield_tilt);
+        self.air_dodge_logic = AirDodgeLogic::new(&settings.air_dodge);
+        self.a_stick = AStick::new(&settings.a_stick);
+        self.b_stick = BStick::new(&settings.b_stick);
+        self.backdash_out_of_crouch_fix = BackdashOutOfCrouchFix::new(&settings.backdash_out_of_crouch_fix);
+        self.safe_grounded_down_b = SafeGroundedDownB::new(&settings.safe_grounded_down_b);
+    }
+
+    pub fn reset_to_neutral(&mut self) {
+        self.controller_state = Default::default();
+        self.action_states = Default::default();
+        self.is_light_shielding = false;
+        self.charge_smash = false;
+        self.active_techniques.clear();
+        self.recent_techniques.clear();
+        self.rebuild_techniques();
+    }
+
     pub fn update_previous_state(&mut self) {
         self.controller_state.update_previous_state();
         self.update_action_buttons_previous_states();
     }
 
+    // None when there is no game state feed, or it has nothing to say right now.
+    pub fn set_game_state(&mut self, game_state: Option<GameState>) {
+        self.game_state = game_state;
+    }
+
+    pub fn game_state(&self) -> Option<&GameState> {
+        self.game_state.as_ref()
+    }
+
+    // Guessed from the last direction held unless the game says otherwise.
+    fn is_facing_right(&self) -> bool {
+        self.game_state.map_or(self.previous_direction_is_right, |game_state| game_state.is_facing_right)
+    }
+
+    // Assumed without a game state feed, which is how the techniques have always behaved.
+    fn is_grounded(&self) -> bool {
+        self.game_state.is_none_or(|game_state| game_state.is_grounded)
+    }
+
+    // The techniques that changed the output during the last process_actions.
+    pub fn active_techniques(&self) -> &[Technique] {
+        &self.active_techniques
+    }
+
+    // The techniques that changed the output lately. They are held for a while,
+    // since most only act for a tick or two and would be missed otherwise.
+    pub fn recent_techniques(&self) -> Vec<Technique> {
+        self.recent_techniques.iter()
+            .filter(|(_, time)| time.elapsed() < RECENT_TECHNIQUE_HOLD_TIME)
+            .map(|(technique, _)| *technique)
+            .collect()
+    }
+
     pub fn process_actions(&mut self) {
+        self.active_techniques.clear();
         self.update_axes_with_directional_buttons();
-        self.handle_x_axis_inversion();
-        self.handle_backdash_out_of_crouch_fix();
-        self.handle_modifier_angles();
-        self.handle_a_stick();
-        self.handle_tilt_modifier();
-        self.handle_b_stick();
-        self.handle_shield_tilt();
-        self.handle_air_dodge_logic();
-        self.handle_angled_smashes();
-        self.handle_charged_smashes();
-        self.handle_jump_logic();
-        self.handle_shield();
+        self.run_technique(Technique::XAxisInversion, Self::handle_x_axis_inversion);
+        self.run_technique(Technique::BackdashOutOfCrouchFix, Self::handle_backdash_out_of_crouch_fix);
+        self.run_technique(Technique::ModifierAngles, Self::handle_modifier_angles);
+        self.run_technique(Technique::AStick, Self::handle_a_stick);
+        self.run_technique(Technique::TiltModifier, Self::handle_tilt_modifier);
+        self.run_technique(Technique::BStick, Self::handle_b_stick);
+        self.run_technique(Technique::ShieldTilt, Self::handle_shield_tilt);
+        self.run_technique(Technique::AirDodge, Self::handle_air_dodge_logic);
+        self.run_technique(Technique::AngledSmash, Self::handle_angled_smashes);
+        self.run_technique(Technique::ChargedSmash, Self::handle_charged_smashes);
+        self.run_technique(Technique::Jump, Self::handle_jump_logic);
+        self.run_technique(Technique::Shield, Self::handle_shield);
 
         self.controller_state.z_button.set_state(self.action_button(Action::Z).is_pressed());
         self.controller_state.l_button.set_state(self.action_button(Action::AirDodge).is_pressed());
@@ -132,6 +243,20 @@ impl DigitalMeleeController {
         self.controller_state.d_up_button.set_state(self.action_button(Action::DUp).is_pressed());
     }
 
+    // Handlers return whether they did something other than pass the actions through.
+    fn run_technique(&mut self, technique: Technique, handler: fn(&mut Self) -> bool) {
+        if handler(self) {
+            let now = Instant::now();
+            self.active_techniques.push(technique);
+            self.recent_techniques.retain(|(recent_technique, _)| *recent_technique != technique);
+            self.recent_techniques.push((technique, now));
+        }
+    }
+
+    fn stick_values(&self) -> [f64; 2] {
+        [self.controller_state.x_axis.value(), self.controller_state.y_axis.value()]
+    }
+
     pub fn update_axes_with_directional_buttons(&mut self) {
         self.controller_state.x_axis.set_value_from_states(
             self.action_button(Action::Left).is_pressed(),
@@ -151,18 +276,22 @@ impl DigitalMeleeController {
         );
     }
 
-    pub fn handle_x_axis_inversion(&mut self) {
-        if self.action_button(Action::InvertXAxis).is_pressed() {
+    pub fn handle_x_axis_inversion(&mut self) -> bool {
+        if self.action_button(Action::InvertXAxis).is_pressed() && self.controller_state.x_axis.value() != 0.0 {
             self.controller_state.x_axis.set_value(-self.controller_state.x_axis.value());
+            return true;
         }
+        false
     }
 
-    pub fn handle_backdash_out_of_crouch_fix(&mut self) {
+    pub fn handle_backdash_out_of_crouch_fix(&mut self) -> bool {
+        let x_value = self.controller_state.x_axis.value();
         self.backdash_out_of_crouch_fix.update_state(
             &self.controller_state.x_axis,
             self.action_button(Action::Left).is_pressed(),
             self.action_button(Action::Right).is_pressed(),
             self.action_button(Action::Down).is_pressed(),
+            self.is_grounded(),
         );
 
         // Only fix backdash out of crouch if you are not doing anything else important.
@@ -179,21 +308,27 @@ impl DigitalMeleeController {
 
             self.controller_state.x_axis.set_value(self.backdash_out_of_crouch_fix.x_axis_output());
         }
+        self.controller_state.x_axis.value() != x_value
     }
 
-    pub fn handle_modifier_angles(&mut self) {
+    pub fn handle_modifier_angles(&mut self) -> bool {
+        let stick_values = self.stick_values();
         if self.action_button(Action::YMod).is_pressed() {
-            self.controller_state.x_axis.set_value(self.controller_state.x_axis.direction() * self.y_mod_x);
-            self.controller_state.y_axis.set_value(self.controller_state.y_axis.direction() * self.y_mod_y);
+            self.controller_state.x_axis.set_value(self.controller_state.x_axis.direction() * self.technique_settings.y_mod_x);
+            self.controller_state.y_axis.set_value(self.controller_state.y_axis.direction() * self.technique_settings.y_mod_y);
         }
         else if self.action_button(Action::XMod).is_pressed() {
-            self.controller_state.x_axis.set_value(self.controller_state.x_axis.direction() * self.x_mod_x);
-            self.controller_state.y_axis.set_value(self.controller_state.y_axis.direction() * self.x_mod_y);
+            self.controller_state.x_axis.set_value(self.controller_state.x_axis.direction() * self.technique_settings.x_mod_x);
+            self.controller_state.y_axis.set_value(self.controller_state.y_axis.direction() * self.technique_settings.x_mod_y);
         }
+        self.stick_values() != stick_values
     }
 
-    pub fn handle_a_stick(&mut self) {
+    pub fn handle_a_stick(&mut self) -> bool {
+        let a = self.action_button(Action::A).is_pressed();
         if self.use_c_stick_tilting && !self.action_button(Action::Shield).is_pressed() {
+            let stick_values = self.stick_values();
+            let c_stick_is_held = self.controller_state.c_x_axis.value() != 0.0 || self.controller_state.c_y_axis.value() != 0.0;
             let a_stick_modifier = self.action_button(Action::Tilt).is_pressed();
             self.a_stick.update_state(
                 &self.controller_state.x_axis,
@@ -211,13 +346,18 @@ impl DigitalMeleeController {
                 self.controller_state.c_x_axis.set_value(0.0);
                 self.controller_state.c_y_axis.set_value(0.0);
             }
+            self.a_stick.output_state() != a
+            || self.stick_values() != stick_values
+            || a_stick_modifier && c_stick_is_held
         }
         else {
-            self.controller_state.a_button.set_state(self.action_button(Action::A).is_pressed());
+            self.controller_state.a_button.set_state(a);
+            false
         }
     }
 
-    pub fn handle_tilt_modifier(&mut self) {
+    pub fn handle_tilt_modifier(&mut self) -> bool {
+        let stick_values = self.stick_values();
         let allow_tilt = self.action_button(Action::Tilt).is_pressed();
         let hold_tilt = self.action_button(Action::Shield).is_pressed();
         self.tilt_modifier.tilt_axes(
@@ -227,9 +367,12 @@ impl DigitalMeleeController {
             false,
             hold_tilt,
         );
+        self.stick_values() != stick_values
     }
 
-    pub fn handle_b_stick(&mut self) {
+    pub fn handle_b_stick(&mut self) -> bool {
+        let stick_values = self.stick_values();
+        let b = self.action_button(Action::B).is_pressed();
         if self.use_extra_b_buttons {
             if self.controller_state.x_axis.value() > 0.0 {
                 self.previous_direction_is_right = true;
@@ -242,8 +385,8 @@ impl DigitalMeleeController {
                 &self.controller_state.x_axis,
                 &self.controller_state.y_axis,
                 self.action_button(Action::B).is_pressed() && !self.action_button(Action::Down).is_pressed(),
-                self.action_button(Action::BSide).is_pressed() && !self.previous_direction_is_right,
-                self.action_button(Action::BSide).is_pressed() && self.previous_direction_is_right,
+                self.action_button(Action::BSide).is_pressed() && !self.is_facing_right(),
+                self.action_button(Action::BSide).is_pressed() && self.is_facing_right(),
                 self.action_button(Action::B).is_pressed() && self.action_button(Action::Down).is_pressed(),
                 self.action_button(Action::BUp).is_pressed(),
                 self.action_button(Action::Shield).is_pressed(),
@@ -251,6 +394,7 @@ impl DigitalMeleeController {
             self.controller_state.b_button.set_state(self.b_stick.output_state());
             self.controller_state.x_axis.set_value(self.b_stick.x_axis_output());
             self.controller_state.y_axis.set_value(self.b_stick.y_axis_output());
+            self.b_stick.output_state() != b || self.stick_values() != stick_values
         }
         else {
             self.safe_grounded_down_b.update_state(
@@ -259,14 +403,17 @@ impl DigitalMeleeController {
                 self.action_button(Action::B).is_pressed(),
                 self.action_button(Action::Down).is_pressed(),
                 self.action_button(Action::Up).is_pressed(),
+                self.is_grounded(),
             );
-            self.controller_state.b_button.set_state(self.action_button(Action::B).is_pressed());
+            self.controller_state.b_button.set_state(b);
             self.controller_state.x_axis.set_value(self.safe_grounded_down_b.x_axis_output());
             self.controller_state.y_axis.set_value(self.safe_grounded_down_b.y_axis_output());
+            self.stick_values() != stick_values
         }
     }
 
-    pub fn handle_shield_tilt(&mut self) {
+    pub fn handle_shield_tilt(&mut self) -> bool {
+        let stick_values = self.stick_values();
         let allow_tilt = self.action_button(Action::Shield).is_pressed();
         let reset_tilt = self.action_button(Action::Shield).just_pressed();
         self.shield_tilter.tilt_axes(
@@ -276,9 +423,11 @@ impl DigitalMeleeController {
             reset_tilt,
             false,
         );
+        self.stick_values() != stick_values
     }
 
-    pub fn handle_air_dodge_logic(&mut self) {
+    pub fn handle_air_dodge_logic(&mut self) -> bool {
+        let stick_values = self.stick_values();
         let air_dodge = self.action_button(Action::AirDodge).is_pressed();
         let shorten_air_dodge = self.action_button(Action::Tilt).is_pressed();
         self.air_dodge_logic.update_axes(
@@ -287,9 +436,10 @@ impl DigitalMeleeController {
             air_dodge,
             shorten_air_dodge,
         );
+        self.stick_values() != stick_values
     }
 
-    pub fn handle_angled_smashes(&mut self) {
+    pub fn handle_angled_smashes(&mut self) -> bool {
         let c_angled = (self.action_button(Action::CLeft).is_pressed()
                      || self.action_button(Action::CRight).is_pressed())
                      &&
@@ -297,11 +447,14 @@ impl DigitalMeleeController {
                      || self.action_button(Action::Up).is_pressed());
 
         if c_angled && !self.action_button(Action::Tilt).is_pressed() {
-            self.controller_state.c_y_axis.set_value(self.controller_state.y_axis.direction() * 0.4);
+            let c_y_value = self.controller_state.c_y_axis.value();
+            self.controller_state.c_y_axis.set_value(self.controller_state.y_axis.direction() * self.technique_settings.angled_smash_c_y_level);
+            return self.controller_state.c_y_axis.value() != c_y_value;
         }
+        false
     }
 
-    pub fn handle_charged_smashes(&mut self) {
+    pub fn handle_charged_smashes(&mut self) -> bool {
         let c_is_pressed = self.action_button(Action::CLeft).is_pressed()
                            || self.action_button(Action::CRight).is_pressed()
                            || self.action_button(Action::CDown).is_pressed()
@@ -316,24 +469,27 @@ impl DigitalMeleeController {
         if self.charge_smash {
             self.controller_state.a_button.set_state(true);
         }
+        self.charge_smash
     }
 
-    pub fn handle_jump_logic(&mut self) {
+    pub fn handle_jump_logic(&mut self) -> bool {
+        let short_hop = self.action_button(Action::ShortHop).is_pressed();
+        let full_hop = self.action_button(Action::FullHop).is_pressed();
         if self.use_short_hop_macro {
-            self.jump_logic.update(
-                self.action_button(Action::ShortHop).is_pressed(),
-                self.action_button(Action::FullHop).is_pressed(),
-            );
+            self.jump_logic.update(short_hop, full_hop);
             self.controller_state.y_button.set_state(self.jump_logic.short_hop_output);
             self.controller_state.x_button.set_state(self.jump_logic.full_hop_output);
+            self.jump_logic.short_hop_output != short_hop || self.jump_logic.full_hop_output != full_hop
         }
         else {
-            self.controller_state.y_button.set_state(self.action_button(Action::ShortHop).is_pressed());
-            self.controller_state.x_button.set_state(self.action_button(Action::FullHop).is_pressed());
+            self.controller_state.y_button.set_state(short_hop);
+            self.controller_state.x_button.set_state(full_hop);
+            false
         }
     }
 
-    pub fn handle_shield(&mut self) {
+    // Only light shield counts, everything else is a straight mapping of a button.
+    pub fn handle_shield(&mut self) -> bool {
         // Allow for a special button to toggle light shield while the shield button is held.
         if self.action_button(Action::ToggleLightShield).just_pressed()
         && self.action_button(Action::Shield).is_pressed() {
@@ -342,25 +498,48 @@ impl DigitalMeleeController {
         if self.action_button(Action::Shield).just_released() {
             self.is_light_shielding = false;
         }
+        let mut analog_value = 0.0;
         if self.is_light_shielding {
             self.controller_state.r_button.set_state(false);
-            self.controller_state.l_analog.set_value((43 + 1) as f64 / 255.0);
+            analog_value = self.technique_settings.light_shield_level;
         }
         else {
             self.controller_state.r_button.set_state(self.action_button(Action::Shield).is_pressed());
+        }
+
+        // Analog shield buttons work on their own and the deepest held level wins.
+        let analog_shield_actions = [Action::AnalogShield1, Action::AnalogShield2, Action::AnalogShield3];
+        for (action, level) in analog_shield_actions.iter().zip(self.technique_settings.analog_shield_levels.iter()) {
+            if self.action_button(*action).is_pressed() {
+                analog_value = f64::max(analog_value, *level);
+            }
+        }
+
+        let shield_slider = self.technique_settings.shield_slider;
+        if shield_slider == ShieldSlider::L || shield_slider == ShieldSlider::Both {
+            self.controller_state.l_analog.set_value(analog_value);
+        }
+        else {
             self.controller_state.l_analog.set_value(0.0);
         }
+        if shield_slider == ShieldSlider::R || shield_slider == ShieldSlider::Both {
+            self.controller_state.r_analog.set_value(analog_value);
+        }
+        else {
+            self.controller_state.r_analog.set_value(0.0);
+        }
+        self.is_light_shielding
     }
 }
 
 impl Default for DigitalMeleeController {
     fn default() -> Self {
-        Self {
+        let mut output = Self {
             controller_state: Default::default(),
             action_states: Default::default(),
             jump_logic: Default::default(),
-            tilt_modifier: StickTilter::new(0.65),
-            shield_tilter: StickTilter::new(0.6625),
+            tilt_modifier: Default::default(),
+            shield_tilter: Default::default(),
             air_dodge_logic: Default::default(),
             a_stick: Default::default(),
             b_stick: Default::default(),
@@ -370,12 +549,15 @@ impl Default for DigitalMeleeController {
             use_c_stick_tilting: true,
             use_extra_b_buttons: true,
             previous_direction_is_right: true,
+            game_state: None,
             is_light_shielding: false,
             charge_smash: false,
-            x_mod_x: 0.2875,
-            x_mod_y: 0.95,
-            y_mod_x: 0.95,
-            y_mod_y: 0.2875,
-        }
+            character_preset: Default::default(),
+            technique_settings: Default::default(),
+            active_techniques: Vec::new(),
+            recent_techniques: Vec::new(),
+        };
+        output.rebuild_techniques();
+        output
     }
 }
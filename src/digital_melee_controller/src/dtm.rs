@@ -0,0 +1,194 @@
+use std::io::{self, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::gamecube_controller_state::GameCubeControllerSnapshot;
+
+const DTM_SIGNATURE: [u8; 4] = [b'D', b'T', b'M', 0x1A];
+const DTM_HEADER_SIZE: usize = 256;
+const CONTROLLER_RECORD_SIZE: usize = 8;
+const GAMECUBE_CPU_CLOCK: u64 = 486_000_000;
+pub const DTM_FRAME_RATE: f64 = 60.0;
+// Melee reads the controllers once a frame, so movies are written with one poll per frame.
+const POLLS_PER_FRAME: u64 = 1;
+
+// Melee reads sticks as (raw - 128) / 80.
+const STICK_CENTER: f64 = 128.0;
+const STICK_STEPS: f64 = 80.0;
+
+// A Dolphin movie with one GameCube controller, one input record per frame.
+// Written movies have no savestate, so Dolphin plays them from boot. A recording
+// only plays back the same if it started as the game booted.
+pub struct DtmMovie {
+    pub game_id: [u8; 6],
+    pub author: String,
+    // 0 to 3 for ports 1 to 4.
+    pub port: u8,
+    pub frames: Vec<GameCubeControllerSnapshot>,
+}
+
+impl DtmMovie {
+    // Melee NTSC 1.02.
+    pub fn new(frames: Vec<GameCubeControllerSnapshot>) -> Self {
+        Self {
+            game_id: *b"GALE01",
+            author: String::new(),
+            port: 0,
+            frames,
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.header())?;
+        for frame in &self.frames {
+            for _ in 0..POLLS_PER_FRAME {
+                writer.write_all(&controller_record(frame))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Reads the inputs of the first connected GameCube controller, one per frame.
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        if data.len() < DTM_HEADER_SIZE || data[0x00..0x04] != DTM_SIGNATURE {
+            return Err(invalid_data("not a DTM movie"));
+        }
+
+        let mut game_id = [0u8; 6];
+        game_id.copy_from_slice(&data[0x04..0x0A]);
+        let author_bytes = &data[0x31..0x51];
+        let author_length = author_bytes.iter().position(|byte| *byte == 0).unwrap_or(author_bytes.len());
+        let author = String::from_utf8_lossy(&author_bytes[..author_length]).into_owned();
+
+        // Each poll has one record per connected controller, in port order.
+        let controllers = data[0x0B] & 0xF;
+        if controllers == 0 {
+            return Err(invalid_data("the movie has no GameCube controllers"));
+        }
+        let port = controllers.trailing_zeros() as u8;
+        let records_per_poll = controllers.count_ones() as usize;
+
+        // The input count has a record for every controller on every poll. A game can
+        // poll more than once a frame, and only the first poll of each frame is kept.
+        let frame_count = read_u64(&data, 0x0D);
+        let input_count = read_u64(&data, 0x15);
+        let polls_per_frame = if frame_count > 0 {
+            ((input_count as f64 / (frame_count as f64 * records_per_poll as f64)).round() as usize).max(1)
+        } else {
+            1
+        };
+
+        let frames = data[DTM_HEADER_SIZE..]
+            .chunks_exact(CONTROLLER_RECORD_SIZE * records_per_poll)
+            .step_by(polls_per_frame)
+            .map(|poll| snapshot_from_record(&poll[..CONTROLLER_RECORD_SIZE]))
+            .collect();
+
+        Ok(Self {
+            game_id,
+            author,
+            port,
+            frames,
+        })
+    }
+
+    // Everything not set here is zero, which tells Dolphin to keep its own settings.
+    // That includes bFromSaveState, so the movie starts from boot.
+    fn header(&self) -> [u8; DTM_HEADER_SIZE] {
+        let mut header = [0u8; DTM_HEADER_SIZE];
+        let frame_count = self.frames.len() as u64;
+        let recording_start_time = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+        let author = self.author.as_bytes();
+        let author_length = author.len().min(32);
+
+        header[0x00..0x04].copy_from_slice(&DTM_SIGNATURE);
+        header[0x04..0x0A].copy_from_slice(&self.game_id);
+        header[0x0B] = 1 << (self.port & 0x3);
+        header[0x0D..0x15].copy_from_slice(&frame_count.to_le_bytes());
+        header[0x15..0x1D].copy_from_slice(&(frame_count * POLLS_PER_FRAME).to_le_bytes());
+        header[0x31..0x31 + author_length].copy_from_slice(&author[..author_length]);
+        header[0x81..0x89].copy_from_slice(&recording_start_time.to_le_bytes());
+        let tick_count = frame_count * (GAMECUBE_CPU_CLOCK / DTM_FRAME_RATE as u64);
+        header[0xED..0xF5].copy_from_slice(&tick_count.to_le_bytes());
+        header
+    }
+}
+
+fn stick_byte(value: f64) -> u8 {
+    (STICK_CENTER + (value * STICK_STEPS).round()).clamp(0.0, 255.0) as u8
+}
+
+fn trigger_byte(value: f64) -> u8 {
+    (value * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+fn stick_value(byte: u8) -> f64 {
+    ((byte as f64 - STICK_CENTER) / STICK_STEPS).clamp(-1.0, 1.0)
+}
+
+fn trigger_value(byte: u8) -> f64 {
+    byte as f64 / 255.0
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Dolphin's ControllerState, with its bitfields packed from the lowest bit up.
+fn controller_record(snapshot: &GameCubeControllerSnapshot) -> [u8; CONTROLLER_RECORD_SIZE] {
+    let buttons_low = snapshot.start as u8
+                    | (snapshot.a as u8) << 1
+                    | (snapshot.b as u8) << 2
+                    | (snapshot.x as u8) << 3
+                    | (snapshot.y as u8) << 4
+                    | (snapshot.z as u8) << 5
+                    | (snapshot.d_up as u8) << 6
+                    | (snapshot.d_down as u8) << 7;
+    let is_connected = true;
+    let buttons_high = snapshot.d_left as u8
+                     | (snapshot.d_right as u8) << 1
+                     | (snapshot.l as u8) << 2
+                     | (snapshot.r as u8) << 3
+                     | (is_connected as u8) << 6;
+    [
+        buttons_low,
+        buttons_high,
+        trigger_byte(snapshot.l_analog),
+        trigger_byte(snapshot.r_analog),
+        stick_byte(snapshot.x_axis),
+        stick_byte(snapshot.y_axis),
+        stick_byte(snapshot.c_x_axis),
+        stick_byte(snapshot.c_y_axis),
+    ]
+}
+
+fn snapshot_from_record(record: &[u8]) -> GameCubeControllerSnapshot {
+    let bit = |byte: usize, bit: u8| record[byte] & (1 << bit) != 0;
+    GameCubeControllerSnapshot {
+        start: bit(0, 0),
+        a: bit(0, 1),
+        b: bit(0, 2),
+        x: bit(0, 3),
+        y: bit(0, 4),
+        z: bit(0, 5),
+        d_up: bit(0, 6),
+        d_down: bit(0, 7),
+        d_left: bit(1, 0),
+        d_right: bit(1, 1),
+        l: bit(1, 2),
+        r: bit(1, 3),
+        l_analog: trigger_value(record[2]),
+        r_analog: trigger_value(record[3]),
+        x_axis: stick_value(record[4]),
+        y_axis: stick_value(record[5]),
+        c_x_axis: stick_value(record[6]),
+        c_y_axis: stick_value(record[7]),
+    }
+}
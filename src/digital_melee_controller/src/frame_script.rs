@@ -0,0 +1,86 @@
+use crate::gamecube_controller_state::GameCubeControllerSnapshot;
+
+// A plain text list of controller inputs, one line per frame:
+//
+//     # Wavedash right.
+//     stick=0,0
+//     3 x
+//     r stick=0.95,-0.2875
+//
+// A line may start with how many frames it lasts. Buttons are held by naming
+// them (a, b, x, y, z, l, r, start, d_left, d_right, d_up, d_down), the sticks
+// are set with stick=x,y and c_stick=x,y, and the triggers with l_analog=value
+// and r_analog=value. Anything not named is neutral.
+pub fn parse_frame_script(text: &str) -> Result<Vec<GameCubeControllerSnapshot>, String> {
+    let mut frames = Vec::new();
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (frame_count, frame) = parse_line(line)
+            .map_err(|e| format!("Line {}: {}", line_index + 1, e))?;
+        frames.extend(std::iter::repeat_n(frame, frame_count));
+    }
+    Ok(frames)
+}
+
+fn parse_line(line: &str) -> Result<(usize, GameCubeControllerSnapshot), String> {
+    let mut tokens = line.split_whitespace().peekable();
+    let frame_count = match tokens.peek().and_then(|token| token.parse::<usize>().ok()) {
+        Some(frame_count) => {
+            tokens.next();
+            frame_count
+        },
+        None => 1,
+    };
+
+    let mut frame = GameCubeControllerSnapshot::default();
+    for token in tokens {
+        let token = token.to_lowercase();
+        match token.split_once('=') {
+            Some((name, value)) => {
+                match name {
+                    "stick" => (frame.x_axis, frame.y_axis) = parse_stick(value)?,
+                    "c_stick" => (frame.c_x_axis, frame.c_y_axis) = parse_stick(value)?,
+                    "l_analog" => frame.l_analog = parse_value(value, 0.0, 1.0)?,
+                    "r_analog" => frame.r_analog = parse_value(value, 0.0, 1.0)?,
+                    _ => return Err(format!("unknown input {}", name)),
+                }
+            },
+            None => {
+                let button = match token.as_str() {
+                    "a" => &mut frame.a,
+                    "b" => &mut frame.b,
+                    "x" => &mut frame.x,
+                    "y" => &mut frame.y,
+                    "z" => &mut frame.z,
+                    "l" => &mut frame.l,
+                    "r" => &mut frame.r,
+                    "start" => &mut frame.start,
+                    "d_left" => &mut frame.d_left,
+                    "d_right" => &mut frame.d_right,
+                    "d_down" => &mut frame.d_down,
+                    "d_up" => &mut frame.d_up,
+                    _ => return Err(format!("unknown button {}", token)),
+                };
+                *button = true;
+            },
+        }
+    }
+    Ok((frame_count, frame))
+}
+
+fn parse_stick(value: &str) -> Result<(f64, f64), String> {
+    match value.split_once(',') {
+        Some((x, y)) => Ok((parse_value(x, -1.0, 1.0)?, parse_value(y, -1.0, 1.0)?)),
+        None => Err(format!("expected x,y but got {}", value)),
+    }
+}
+
+fn parse_value(value: &str, min: f64, max: f64) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        _ => Err(format!("expected a number from {} to {} but got {}", min, max, value)),
+    }
+}
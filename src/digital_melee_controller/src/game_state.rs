@@ -0,0 +1,48 @@
+use serde::{Serialize, Deserialize};
+
+use crate::character_preset::Character;
+
+// What the game says about the player, from a live feed. Without one, techniques
+// fall back to guessing from the keys.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameState {
+    pub character: Character,
+    pub action_state: u16,
+    pub is_facing_right: bool,
+    pub is_grounded: bool,
+}
+
+impl Character {
+    // Melee's internal character IDs. Nana is reported as IceClimbers too.
+    pub fn from_internal_id(id: u8) -> Option<Self> {
+        Some(match id {
+            0x00 => Character::Mario,
+            0x01 => Character::Fox,
+            0x02 => Character::CaptainFalcon,
+            0x03 => Character::DonkeyKong,
+            0x04 => Character::Kirby,
+            0x05 => Character::Bowser,
+            0x06 => Character::Link,
+            0x07 => Character::Sheik,
+            0x08 => Character::Ness,
+            0x09 => Character::Peach,
+            0x0A | 0x0B => Character::IceClimbers,
+            0x0C => Character::Pikachu,
+            0x0D => Character::Samus,
+            0x0E => Character::Yoshi,
+            0x0F => Character::Jigglypuff,
+            0x10 => Character::Mewtwo,
+            0x11 => Character::Luigi,
+            0x12 => Character::Marth,
+            0x13 => Character::Zelda,
+            0x14 => Character::YoungLink,
+            0x15 => Character::DrMario,
+            0x16 => Character::Falco,
+            0x17 => Character::Pichu,
+            0x18 => Character::GameAndWatch,
+            0x19 => Character::Ganondorf,
+            0x1A => Character::Roy,
+            _ => return None,
+        })
+    }
+}
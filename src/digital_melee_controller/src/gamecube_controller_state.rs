@@ -34,6 +34,30 @@ pub enum GameCubeControllerSlider {
     R,
 }
 
+// Just the values of a controller state, without any history, so they can be
+// compared, recorded and played back.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct GameCubeControllerSnapshot {
+    pub a: bool,
+    pub b: bool,
+    pub x: bool,
+    pub y: bool,
+    pub z: bool,
+    pub l: bool,
+    pub r: bool,
+    pub start: bool,
+    pub d_left: bool,
+    pub d_right: bool,
+    pub d_down: bool,
+    pub d_up: bool,
+    pub x_axis: f64,
+    pub y_axis: f64,
+    pub c_x_axis: f64,
+    pub c_y_axis: f64,
+    pub l_analog: f64,
+    pub r_analog: f64,
+}
+
 #[derive(Default)]
 pub struct GameCubeControllerState {
     pub x_axis: AnalogAxis,
@@ -78,6 +102,50 @@ impl GameCubeControllerState {
         self.r_analog.update_previous_state();
     }
 
+    pub fn snapshot(&self) -> GameCubeControllerSnapshot {
+        GameCubeControllerSnapshot {
+            a: self.a_button.is_pressed(),
+            b: self.b_button.is_pressed(),
+            x: self.x_button.is_pressed(),
+            y: self.y_button.is_pressed(),
+            z: self.z_button.is_pressed(),
+            l: self.l_button.is_pressed(),
+            r: self.r_button.is_pressed(),
+            start: self.start_button.is_pressed(),
+            d_left: self.d_left_button.is_pressed(),
+            d_right: self.d_right_button.is_pressed(),
+            d_down: self.d_down_button.is_pressed(),
+            d_up: self.d_up_button.is_pressed(),
+            x_axis: self.x_axis.value(),
+            y_axis: self.y_axis.value(),
+            c_x_axis: self.c_x_axis.value(),
+            c_y_axis: self.c_y_axis.value(),
+            l_analog: self.l_analog.value(),
+            r_analog: self.r_analog.value(),
+        }
+    }
+
+    pub fn set_from_snapshot(&mut self, snapshot: &GameCubeControllerSnapshot) {
+        self.a_button.set_state(snapshot.a);
+        self.b_button.set_state(snapshot.b);
+        self.x_button.set_state(snapshot.x);
+        self.y_button.set_state(snapshot.y);
+        self.z_button.set_state(snapshot.z);
+        self.l_button.set_state(snapshot.l);
+        self.r_button.set_state(snapshot.r);
+        self.start_button.set_state(snapshot.start);
+        self.d_left_button.set_state(snapshot.d_left);
+        self.d_right_button.set_state(snapshot.d_right);
+        self.d_down_button.set_state(snapshot.d_down);
+        self.d_up_button.set_state(snapshot.d_up);
+        self.x_axis.set_value(snapshot.x_axis);
+        self.y_axis.set_value(snapshot.y_axis);
+        self.c_x_axis.set_value(snapshot.c_x_axis);
+        self.c_y_axis.set_value(snapshot.c_y_axis);
+        self.l_analog.set_value(snapshot.l_analog);
+        self.r_analog.set_value(snapshot.r_analog);
+    }
+
     pub fn button(&self, input: GameCubeControllerButton) -> &Button {
         match input {
             GameCubeControllerButton::A => &self.a_button,
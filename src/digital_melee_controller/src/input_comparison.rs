@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use crate::gamecube_controller_state::GameCubeControllerSnapshot;
+
+// Melee ignores each stick axis below 23 / 80 and pulls anything past the rim back onto it.
+const STICK_STEPS: f64 = 80.0;
+const STICK_DEADZONE_STEPS: f64 = 23.0;
+
+// How far either side of the first input to look for the best alignment.
+const ALIGNMENT_SEARCH_FRAMES: i64 = 30;
+
+// Past this the stick is pointing somewhere else, not just landing on a neighbouring notch.
+const ANGLE_TOLERANCE_DEGREES: f64 = 0.5;
+
+type ButtonReader = fn(&GameCubeControllerSnapshot) -> bool;
+
+const BUTTONS: [(&str, ButtonReader); 12] = [
+    ("A", |s| s.a),
+    ("B", |s| s.b),
+    ("X", |s| s.x),
+    ("Y", |s| s.y),
+    ("Z", |s| s.z),
+    ("L", |s| s.l),
+    ("R", |s| s.r),
+    ("Start", |s| s.start),
+    ("DLeft", |s| s.d_left),
+    ("DRight", |s| s.d_right),
+    ("DDown", |s| s.d_down),
+    ("DUp", |s| s.d_up),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputMismatch {
+    Button { button: &'static str, expected: bool },
+    // Both sticks were tilted but in different directions, like a drifting wavedash angle.
+    StickAngle { expected_degrees: f64, actual_degrees: f64 },
+    // Same direction but a different distance, like a tilt that came out as a smash.
+    StickDistance { expected: f64, actual: f64 },
+    CStick { expected: (f64, f64), actual: (f64, f64) },
+    Trigger { expected: f64, actual: f64 },
+    // How long jump was held from the frame it was pressed, short hops need it released early.
+    JumpLength { expected_frames: usize, actual_frames: usize },
+}
+
+impl InputMismatch {
+    fn kind(&self) -> &'static str {
+        match self {
+            InputMismatch::Button { .. } => "Button",
+            InputMismatch::StickAngle { .. } => "StickAngle",
+            InputMismatch::StickDistance { .. } => "StickDistance",
+            InputMismatch::CStick { .. } => "CStick",
+            InputMismatch::Trigger { .. } => "Trigger",
+            InputMismatch::JumpLength { .. } => "JumpLength",
+        }
+    }
+
+    fn description(&self) -> String {
+        match self {
+            InputMismatch::Button { button, expected } => {
+                format!("{} was {} but the game saw it {}", button, pressed_text(*expected), pressed_text(!expected))
+            },
+            InputMismatch::StickAngle { expected_degrees, actual_degrees } => {
+                format!("stick angle {:.2} deg but the game saw {:.2} deg", expected_degrees, actual_degrees)
+            },
+            InputMismatch::StickDistance { expected, actual } => {
+                format!("stick distance {:.4} but the game saw {:.4}", expected, actual)
+            },
+            InputMismatch::CStick { expected, actual } => {
+                format!("c-stick ({:.4}, {:.4}) but the game saw ({:.4}, {:.4})", expected.0, expected.1, actual.0, actual.1)
+            },
+            InputMismatch::Trigger { expected, actual } => {
+                format!("analog trigger {:.4} but the game saw {:.4}", expected, actual)
+            },
+            InputMismatch::JumpLength { expected_frames, actual_frames } => {
+                format!("jump held {} frames but the game saw {} frames", expected_frames, actual_frames)
+            },
+        }
+    }
+}
+
+// The result of lining up what was sent against what the game saw.
+pub struct InputComparison {
+    // Game frame = sent frame + offset.
+    pub offset: i64,
+    pub compared_frames: usize,
+    // Sent frame and what was wrong with it.
+    pub mismatches: Vec<(usize, InputMismatch)>,
+}
+
+impl InputComparison {
+    pub fn print(&self, max_mismatches: usize) {
+        println!("Compared {} frames at an offset of {} frames.", self.compared_frames, self.offset);
+        if self.mismatches.is_empty() {
+            println!("Every input matched.");
+            return;
+        }
+
+        let mut counts = BTreeMap::new();
+        for (_, mismatch) in &self.mismatches {
+            *counts.entry(mismatch.kind()).or_insert(0) += 1;
+        }
+        println!("Mismatches:");
+        for (kind, count) in &counts {
+            println!("  {:<20} {}", kind, count);
+        }
+
+        for (frame, mismatch) in self.mismatches.iter().take(max_mismatches) {
+            println!("  frame {:>6}: {}", frame, mismatch.description());
+        }
+        if self.mismatches.len() > max_mismatches {
+            println!("  ...and {} more.", self.mismatches.len() - max_mismatches);
+        }
+    }
+}
+
+// The stick position the game ends up using.
+pub fn melee_processed_stick(x: f64, y: f64) -> (f64, f64) {
+    let axis = |value: f64| {
+        let steps = (value * STICK_STEPS).round();
+        if steps.abs() < STICK_DEADZONE_STEPS { 0.0 } else { steps }
+    };
+    let (x, y) = (axis(x), axis(y));
+    let magnitude = x.hypot(y);
+    if magnitude > STICK_STEPS {
+        let scale = STICK_STEPS / magnitude;
+        (x * scale / STICK_STEPS, y * scale / STICK_STEPS)
+    } else {
+        (x / STICK_STEPS, y / STICK_STEPS)
+    }
+}
+
+// expected is what we sent and actual is what the game saw, both one snapshot per frame.
+pub fn compare_inputs(expected: &[GameCubeControllerSnapshot], actual: &[GameCubeControllerSnapshot]) -> InputComparison {
+    let offset = best_alignment(expected, actual);
+    let mut comparison = InputComparison {
+        offset,
+        compared_frames: 0,
+        mismatches: Vec::new(),
+    };
+    for (frame, expected_frame) in expected.iter().enumerate() {
+        let actual_frame = match aligned_frame(actual, frame, offset) {
+            Some(actual_frame) => actual_frame,
+            None => continue,
+        };
+        comparison.compared_frames += 1;
+        for mismatch in frame_mismatches(expected_frame, actual_frame) {
+            comparison.mismatches.push((frame, mismatch));
+        }
+        if is_jump_press(expected, frame) {
+            let expected_frames = jump_hold_length(expected, frame, 0);
+            let actual_frames = jump_hold_length(actual, frame, offset);
+            if expected_frames != actual_frames {
+                comparison.mismatches.push((frame, InputMismatch::JumpLength { expected_frames, actual_frames }));
+            }
+        }
+    }
+    comparison
+}
+
+fn frame_mismatches(expected: &GameCubeControllerSnapshot, actual: &GameCubeControllerSnapshot) -> Vec<InputMismatch> {
+    let mut mismatches = Vec::new();
+    for (button, is_pressed) in BUTTONS {
+        if is_pressed(expected) != is_pressed(actual) {
+            mismatches.push(InputMismatch::Button { button, expected: is_pressed(expected) });
+        }
+    }
+
+    let expected_stick = melee_processed_stick(expected.x_axis, expected.y_axis);
+    let actual_stick = melee_processed_stick(actual.x_axis, actual.y_axis);
+    if !same_stick(expected_stick, actual_stick) {
+        let is_tilted = |stick: (f64, f64)| stick != (0.0, 0.0);
+        let angle_difference = (stick_angle(expected_stick) - stick_angle(actual_stick)).abs();
+        let angle_difference = angle_difference.min(360.0 - angle_difference);
+        if is_tilted(expected_stick) && is_tilted(actual_stick) && angle_difference > ANGLE_TOLERANCE_DEGREES {
+            mismatches.push(InputMismatch::StickAngle {
+                expected_degrees: stick_angle(expected_stick),
+                actual_degrees: stick_angle(actual_stick),
+            });
+        } else {
+            mismatches.push(InputMismatch::StickDistance {
+                expected: expected_stick.0.hypot(expected_stick.1),
+                actual: actual_stick.0.hypot(actual_stick.1),
+            });
+        }
+    }
+
+    let expected_c_stick = melee_processed_stick(expected.c_x_axis, expected.c_y_axis);
+    let actual_c_stick = melee_processed_stick(actual.c_x_axis, actual.c_y_axis);
+    if !same_stick(expected_c_stick, actual_c_stick) {
+        mismatches.push(InputMismatch::CStick { expected: expected_c_stick, actual: actual_c_stick });
+    }
+
+    // The game scales the triggers on its own, so only whether one is pressed at all is compared.
+    let expected_trigger = expected.l_analog.max(expected.r_analog);
+    let actual_trigger = actual.l_analog.max(actual.r_analog);
+    if (expected_trigger > 0.0) != (actual_trigger > 0.0) {
+        mismatches.push(InputMismatch::Trigger { expected: expected_trigger, actual: actual_trigger });
+    }
+
+    mismatches
+}
+
+// Lines up the first non-neutral input of each, then picks the nearby offset with the fewest mismatches.
+fn best_alignment(expected: &[GameCubeControllerSnapshot], actual: &[GameCubeControllerSnapshot]) -> i64 {
+    let first_input = |frames: &[GameCubeControllerSnapshot]| {
+        frames.iter().position(|frame| *frame != GameCubeControllerSnapshot::default()).unwrap_or(0) as i64
+    };
+    let rough_offset = first_input(actual) - first_input(expected);
+
+    let mut best_offset = rough_offset;
+    let mut best_score = usize::MAX;
+    for offset in rough_offset - ALIGNMENT_SEARCH_FRAMES..=rough_offset + ALIGNMENT_SEARCH_FRAMES {
+        // Frames that fall outside the other side count against the offset too.
+        let score: usize = expected.iter().enumerate()
+            .map(|(frame, expected_frame)| match aligned_frame(actual, frame, offset) {
+                Some(actual_frame) => frame_mismatches(expected_frame, actual_frame).len(),
+                None => 1,
+            })
+            .sum();
+        if score < best_score {
+            best_score = score;
+            best_offset = offset;
+        }
+    }
+    best_offset
+}
+
+fn aligned_frame(frames: &[GameCubeControllerSnapshot], frame: usize, offset: i64) -> Option<&GameCubeControllerSnapshot> {
+    usize::try_from(frame as i64 + offset).ok().and_then(|frame| frames.get(frame))
+}
+
+fn is_jump_held(frame: &GameCubeControllerSnapshot) -> bool {
+    frame.x || frame.y
+}
+
+fn is_jump_press(frames: &[GameCubeControllerSnapshot], frame: usize) -> bool {
+    is_jump_held(&frames[frame]) && (frame == 0 || !is_jump_held(&frames[frame - 1]))
+}
+
+fn jump_hold_length(frames: &[GameCubeControllerSnapshot], frame: usize, offset: i64) -> usize {
+    (frame..)
+        .map_while(|frame| aligned_frame(frames, frame, offset))
+        .take_while(|frame| is_jump_held(frame))
+        .count()
+}
+
+fn same_stick(a: (f64, f64), b: (f64, f64)) -> bool {
+    (a.0 - b.0).abs() < 0.5 / STICK_STEPS && (a.1 - b.1).abs() < 0.5 / STICK_STEPS
+}
+
+fn stick_angle(stick: (f64, f64)) -> f64 {
+    stick.1.atan2(stick.0).to_degrees().rem_euclid(360.0)
+}
+
+fn pressed_text(is_pressed: bool) -> &'static str {
+    if is_pressed { "pressed" } else { "released" }
+}
@@ -1,6 +1,8 @@
 use std::time::{Instant, Duration};
 
 use crate::button::Button;
+use crate::character_preset::{CharacterPreset, frames_to_duration};
+use crate::technique_settings::TechniqueSettings;
 
 pub struct JumpLogic {
     pub short_hop_output: bool,
@@ -12,10 +14,28 @@ pub struct JumpLogic {
     is_full_hopping: bool,
     short_hop_time: Instant,
     full_hop_time: Instant,
+    short_hop_duration: Duration,
+    full_hop_duration: Duration,
+    full_hop_release_duration: Duration,
 }
 
 impl Default for JumpLogic {
     fn default() -> Self {
+        let preset = CharacterPreset::default();
+        Self::new(
+            frames_to_duration(TechniqueSettings::default().short_hop_release_frame),
+            preset.full_hop_duration(),
+            preset.full_hop_release_duration(),
+        )
+    }
+}
+
+impl JumpLogic {
+    pub fn new(
+        short_hop_duration: Duration,
+        full_hop_duration: Duration,
+        full_hop_release_duration: Duration,
+    ) -> Self {
         Self {
             short_hop_output: false,
             full_hop_output: false,
@@ -25,11 +45,12 @@ impl Default for JumpLogic {
             is_full_hopping: false,
             short_hop_time: Instant::now(),
             full_hop_time: Instant::now(),
+            short_hop_duration,
+            full_hop_duration,
+            full_hop_release_duration,
         }
     }
-}
 
-impl JumpLogic {
     pub fn update(&mut self, short_hop: bool, full_hop: bool) {
         self.short_hop_input.update_previous_state();
         self.full_hop_input.update_previous_state();
@@ -47,7 +68,7 @@ impl JumpLogic {
             self.short_hop_time = Instant::now();
         }
 
-        if self.is_short_hopping && Instant::now() - self.short_hop_time >= Duration::from_millis(25) {
+        if self.is_short_hopping && Instant::now() - self.short_hop_time >= self.short_hop_duration {
             self.short_hop_output = false;
             self.is_short_hopping = false;
         }
@@ -63,13 +84,13 @@ impl JumpLogic {
         }
 
         if self.is_full_hopping && !self.full_hop_input.is_pressed() {
-            if Instant::now() - self.full_hop_time >= Duration::from_millis(134) {
+            if Instant::now() - self.full_hop_time >= self.full_hop_duration {
                 self.full_hop_output = false;
             }
 
             // Wait one extra frame so you can't miss a double jump by
             // pushing the full hop button on the same frame of release.
-            if Instant::now() - self.full_hop_time >= Duration::from_millis(150) {
+            if Instant::now() - self.full_hop_time >= self.full_hop_release_duration {
                 self.is_full_hopping = false;
             }
         }
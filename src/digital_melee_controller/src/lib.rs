@@ -10,7 +10,14 @@ mod a_stick;
 mod b_stick;
 mod backdash_out_of_crouch_fix;
 mod safe_grounded_down_b;
+mod character_preset;
+mod technique_settings;
 mod digital_melee_controller;
+mod dtm;
+mod frame_script;
+mod game_state;
+mod slippi_replay;
+mod input_comparison;
 
 pub use crate::button::Button;
 pub use crate::analog_axis::AnalogAxis;
@@ -20,9 +27,41 @@ pub use crate::gamecube_controller_state::{
     GameCubeControllerAxis,
     GameCubeControllerSlider,
     GameCubeControllerState,
+    GameCubeControllerSnapshot,
 };
+pub use crate::character_preset::{
+    Character,
+    CharacterPreset,
+    CharacterPresetOverrides,
+};
+pub use crate::technique_settings::{TechniqueSettings, ShieldSlider};
+pub use crate::stick_tilter::StickTilterSettings;
+pub use crate::air_dodge_logic::AirDodgeSettings;
+pub use crate::a_stick::AStickSettings;
+pub use crate::b_stick::BStickSettings;
+pub use crate::backdash_out_of_crouch_fix::BackdashOutOfCrouchFixSettings;
+pub use crate::safe_grounded_down_b::SafeGroundedDownBSettings;
 pub use crate::digital_melee_controller::{
     DigitalMeleeController,
     Action,
     ActionStates,
+    Technique,
+};
+pub use crate::dtm::{DtmMovie, DTM_FRAME_RATE};
+pub use crate::frame_script::parse_frame_script;
+pub use crate::game_state::GameState;
+pub use crate::slippi_replay::{
+    SlippiReplay,
+    SlippiEventStream,
+    SLIPPI_FIRST_FRAME,
+    game_state_from_event,
+    is_game_start_event,
+    is_game_end_event,
+    slippi_replay_header_size,
+};
+pub use crate::input_comparison::{
+    InputComparison,
+    InputMismatch,
+    compare_inputs,
+    melee_processed_stick,
 };
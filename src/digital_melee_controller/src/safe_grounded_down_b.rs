@@ -1,8 +1,28 @@
 use std::time::{Instant, Duration};
 
+use serde::{Serialize, Deserialize};
+
 use crate::button::Button;
 use crate::analog_axis::AnalogAxis;
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafeGroundedDownBSettings {
+    pub safe_b_duration_ms: u64,
+    pub x_level: f64,
+    pub y_level: f64,
+}
+
+impl Default for SafeGroundedDownBSettings {
+    fn default() -> Self {
+        Self {
+            safe_b_duration_ms: 25,
+            x_level: 0.5875,
+            y_level: 0.6,
+        }
+    }
+}
+
 pub struct SafeGroundedDownB {
     x_axis_output: f64,
     y_axis_output: f64,
@@ -10,9 +30,24 @@ pub struct SafeGroundedDownB {
     is_doing_safe_b: bool,
     safe_b_time: Instant,
     safe_b_duration: Duration,
+    x_level: f64,
+    y_level: f64,
 }
 
 impl SafeGroundedDownB {
+    pub fn new(settings: &SafeGroundedDownBSettings) -> Self {
+        Self {
+            safe_b_duration: Duration::from_millis(settings.safe_b_duration_ms),
+            x_level: settings.x_level,
+            y_level: settings.y_level,
+            x_axis_output: 0.0,
+            y_axis_output: 0.0,
+            b_input: Default::default(),
+            is_doing_safe_b: false,
+            safe_b_time: Instant::now(),
+        }
+    }
+
     pub fn x_axis_output(&self) -> f64 { self.x_axis_output }
     pub fn y_axis_output(&self) -> f64 { self.y_axis_output }
 
@@ -23,6 +58,7 @@ impl SafeGroundedDownB {
         b: bool,
         down: bool,
         up: bool,
+        is_grounded: bool,
     ) {
         self.x_axis_output = x_axis.value();
         self.y_axis_output = y_axis.value();
@@ -30,15 +66,15 @@ impl SafeGroundedDownB {
         self.b_input.update_previous_state();
         self.b_input.set_state(b);
 
-        if self.b_input.just_pressed() && (down || up) {
+        if self.b_input.just_pressed() && (down || up) && is_grounded {
             self.is_doing_safe_b = true;
             self.safe_b_time = Instant::now();
         }
 
         if self.is_doing_safe_b {
             if Instant::now() - self.safe_b_time < self.safe_b_duration {
-                self.x_axis_output = x_axis.direction() * 0.5875;
-                self.y_axis_output = y_axis.direction() * 0.6;
+                self.x_axis_output = x_axis.direction() * self.x_level;
+                self.y_axis_output = y_axis.direction() * self.y_level;
             }
             else {
                 self.is_doing_safe_b = false;
@@ -49,13 +85,6 @@ impl SafeGroundedDownB {
 
 impl Default for SafeGroundedDownB {
     fn default() -> Self {
-        Self {
-            x_axis_output: 0.0,
-            y_axis_output: 0.0,
-            b_input: Default::default(),
-            is_doing_safe_b: false,
-            safe_b_time: Instant::now(),
-            safe_b_duration: Duration::from_millis(25),
-        }
+        Self::new(&SafeGroundedDownBSettings::default())
     }
 }
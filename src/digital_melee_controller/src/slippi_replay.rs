@@ -0,0 +1,220 @@
+use std::io::{self, Read};
+
+use crate::character_preset::Character;
+use crate::gamecube_controller_state::GameCubeControllerSnapshot;
+use crate::game_state::GameState;
+
+// Slippi replays are UBJSON with the game's event stream stored as a byte array under "raw".
+const RAW_ELEMENT_PREFIX: &[u8] = b"{U\x03raw[$U#l";
+
+const EVENT_PAYLOADS: u8 = 0x35;
+const GAME_START: u8 = 0x36;
+const PRE_FRAME_UPDATE: u8 = 0x37;
+const POST_FRAME_UPDATE: u8 = 0x38;
+const GAME_END: u8 = 0x39;
+
+// Every replay starts counting frames here, the first frame players can act on is 0.
+pub const SLIPPI_FIRST_FRAME: i32 = -123;
+
+// The inputs of one player in a Slippi replay, one per frame from SLIPPI_FIRST_FRAME.
+// Sticks are as the game processed them, triggers and buttons are as they were pressed.
+pub struct SlippiReplay {
+    pub port: u8,
+    pub frames: Vec<GameCubeControllerSnapshot>,
+}
+
+impl SlippiReplay {
+    // port is 0 to 3 for ports 1 to 4. Ice Climbers followers are left out.
+    pub fn read<R: Read>(reader: &mut R, port: u8) -> io::Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let mut events = SlippiEventStream::default();
+        events.push(raw_events(&data)?);
+
+        let mut frames = Vec::new();
+        while let Some(event) = events.next_event()? {
+            if is_player_event(event, PRE_FRAME_UPDATE, port) {
+                // Rollback can update a frame more than once, the last update is what the game kept.
+                let frame = read_i32(event, 0x1) - SLIPPI_FIRST_FRAME;
+                if frame >= 0 {
+                    let frame = frame as usize;
+                    if frames.len() <= frame {
+                        frames.resize(frame + 1, GameCubeControllerSnapshot::default());
+                    }
+                    frames[frame] = snapshot_from_pre_frame_update(event);
+                }
+            }
+        }
+
+        if frames.is_empty() {
+            return Err(invalid_data(&format!("port {} has no inputs", port + 1)));
+        }
+        Ok(Self {
+            port,
+            frames,
+        })
+    }
+}
+
+// Splits Slippi's event stream into events as bytes arrive, from a replay or a live game.
+pub struct SlippiEventStream {
+    payload_sizes: [Option<usize>; 256],
+    buffer: Vec<u8>,
+    offset: usize,
+}
+
+impl SlippiEventStream {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.drain(..self.offset);
+        self.offset = 0;
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    // The next whole event with its command byte first, or None until more bytes are pushed.
+    pub fn next_event(&mut self) -> io::Result<Option<&[u8]>> {
+        let remaining = &self.buffer[self.offset..];
+        let command = match remaining.first() {
+            Some(command) => *command,
+            None => return Ok(None),
+        };
+        let size = if command == EVENT_PAYLOADS {
+            match remaining.get(1) {
+                Some(size) => *size as usize,
+                None => return Ok(None),
+            }
+        } else {
+            match self.payload_sizes[command as usize] {
+                Some(size) => size,
+                None => return Err(invalid_data(&format!("unknown event 0x{:02X}", command))),
+            }
+        };
+        if remaining.len() < 1 + size {
+            return Ok(None);
+        }
+
+        let event = self.offset..self.offset + 1 + size;
+        self.offset = event.end;
+        let event = &self.buffer[event];
+        if command == EVENT_PAYLOADS {
+            if size == 0 {
+                return Err(invalid_data("the event sizes are missing"));
+            }
+            // A new game says how big each of its events are before anything else.
+            self.payload_sizes = [None; 256];
+            self.payload_sizes[EVENT_PAYLOADS as usize] = Some(size);
+            for entry in event[2..].chunks_exact(3) {
+                self.payload_sizes[entry[0] as usize] = Some(u16::from_be_bytes([entry[1], entry[2]]) as usize);
+            }
+        }
+        Ok(Some(event))
+    }
+}
+
+impl Default for SlippiEventStream {
+    fn default() -> Self {
+        Self {
+            payload_sizes: [None; 256],
+            buffer: Vec::new(),
+            offset: 0,
+        }
+    }
+}
+
+// The state of a player after a frame, port is 0 to 3 for ports 1 to 4.
+pub fn game_state_from_event(event: &[u8], port: u8) -> Option<GameState> {
+    if !is_player_event(event, POST_FRAME_UPDATE, port) {
+        return None;
+    }
+    Some(GameState {
+        character: Character::from_internal_id(read_bytes::<1>(event, 0x7)[0]).unwrap_or_default(),
+        action_state: read_u16(event, 0x8),
+        is_facing_right: read_f32(event, 0x12) > 0.0,
+        // Replays older than 2.0.0 do not say, so they read as grounded.
+        is_grounded: read_bytes::<1>(event, 0x2F)[0] == 0,
+    })
+}
+
+pub fn is_game_start_event(event: &[u8]) -> bool {
+    event.first() == Some(&GAME_START)
+}
+
+pub fn is_game_end_event(event: &[u8]) -> bool {
+    event.first() == Some(&GAME_END)
+}
+
+// Event sizes come from the stream itself, so an event can be too short to say whose it is.
+// The byte after the port is set for the Ice Climbers follower.
+fn is_player_event(event: &[u8], command: u8, port: u8) -> bool {
+    event.first() == Some(&command) && event.get(0x5) == Some(&port) && event.get(0x6) == Some(&0)
+}
+
+// Where the events start in a replay file, which can still be being written.
+pub fn slippi_replay_header_size(data: &[u8]) -> Option<usize> {
+    let header_size = RAW_ELEMENT_PREFIX.len() + 4;
+    if data.len() < header_size || !data.starts_with(RAW_ELEMENT_PREFIX) {
+        return None;
+    }
+    Some(header_size)
+}
+
+fn raw_events(data: &[u8]) -> io::Result<&[u8]> {
+    let length_start = RAW_ELEMENT_PREFIX.len();
+    let header_size = slippi_replay_header_size(data).ok_or_else(|| invalid_data("not a Slippi replay"))?;
+    let events = &data[header_size..];
+    // The length is left at 0 until the game ends.
+    let length = read_i32(data, length_start) as usize;
+    if length == 0 || length > events.len() {
+        return Ok(events);
+    }
+    Ok(&events[..length])
+}
+
+fn snapshot_from_pre_frame_update(event: &[u8]) -> GameCubeControllerSnapshot {
+    let buttons = read_u16(event, 0x31);
+    let button = |mask: u16| buttons & mask != 0;
+    GameCubeControllerSnapshot {
+        d_left: button(0x0001),
+        d_right: button(0x0002),
+        d_down: button(0x0004),
+        d_up: button(0x0008),
+        z: button(0x0010),
+        r: button(0x0020),
+        l: button(0x0040),
+        a: button(0x0100),
+        b: button(0x0200),
+        x: button(0x0400),
+        y: button(0x0800),
+        start: button(0x1000),
+        x_axis: read_f32(event, 0x19),
+        y_axis: read_f32(event, 0x1D),
+        c_x_axis: read_f32(event, 0x21),
+        c_y_axis: read_f32(event, 0x25),
+        l_analog: read_f32(event, 0x33),
+        r_analog: read_f32(event, 0x37),
+    }
+}
+
+// Fields that older replay versions did not have read as zero.
+fn read_bytes<const N: usize>(event: &[u8], offset: usize) -> [u8; N] {
+    let mut bytes = [0u8; N];
+    if let Some(field) = event.get(offset..offset + N) {
+        bytes.copy_from_slice(field);
+    }
+    bytes
+}
+
+fn read_u16(event: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes(read_bytes(event, offset))
+}
+
+fn read_i32(event: &[u8], offset: usize) -> i32 {
+    i32::from_be_bytes(read_bytes(event, offset))
+}
+
+fn read_f32(event: &[u8], offset: usize) -> f64 {
+    f32::from_be_bytes(read_bytes(event, offset)) as f64
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
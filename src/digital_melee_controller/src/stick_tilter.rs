@@ -1,18 +1,37 @@
 use std::time::{Instant, Duration};
 
+use serde::{Serialize, Deserialize};
+
 use crate::analog_axis::AnalogAxis;
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StickTilterSettings {
+    pub tilt_duration_ms: u64,
+}
+
+impl Default for StickTilterSettings {
+    fn default() -> Self {
+        Self {
+            tilt_duration_ms: 117,
+        }
+    }
+}
+
 pub struct StickTilter {
     is_tilting: bool,
     tilt_level: f64,
     tilt_time: Instant,
+    tilt_duration: Duration,
 }
 
 impl StickTilter {
-    pub fn new(tilt_level: f64) -> Self {
+    pub fn new(tilt_level: f64, settings: &StickTilterSettings) -> Self {
         Self {
             tilt_level,
-            ..Default::default()
+            tilt_duration: Duration::from_millis(settings.tilt_duration_ms),
+            is_tilting: false,
+            tilt_time: Instant::now(),
         }
     }
 
@@ -36,7 +55,7 @@ impl StickTilter {
         if self.is_tilting || (allow_tilt && hold_tilt) {
             set_magnitude(x_axis, y_axis, self.tilt_level);
 
-            if Instant::now() - self.tilt_time >= Duration::from_millis(117) {
+            if Instant::now() - self.tilt_time >= self.tilt_duration {
                 self.is_tilting = false;
             }
         }
@@ -45,11 +64,7 @@ impl StickTilter {
 
 impl Default for StickTilter {
     fn default() -> Self {
-        Self {
-            is_tilting: false,
-            tilt_level: 1.0,
-            tilt_time: Instant::now(),
-        }
+        Self::new(1.0, &StickTilterSettings::default())
     }
 }
 
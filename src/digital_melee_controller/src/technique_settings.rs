@@ -0,0 +1,63 @@
+use serde::{Serialize, Deserialize};
+
+use crate::stick_tilter::StickTilterSettings;
+use crate::air_dodge_logic::AirDodgeSettings;
+use crate::a_stick::AStickSettings;
+use crate::b_stick::BStickSettings;
+use crate::backdash_out_of_crouch_fix::BackdashOutOfCrouchFixSettings;
+use crate::safe_grounded_down_b::SafeGroundedDownBSettings;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ShieldSlider {
+    L,
+    R,
+    Both,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TechniqueSettings {
+    pub tilt: StickTilterSettings,
+    pub shield_tilt: StickTilterSettings,
+    pub air_dodge: AirDodgeSettings,
+    pub a_stick: AStickSettings,
+    pub b_stick: BStickSettings,
+    pub backdash_out_of_crouch_fix: BackdashOutOfCrouchFixSettings,
+    pub safe_grounded_down_b: SafeGroundedDownBSettings,
+    pub short_hop_release_frame: f64,
+    pub tilt_level: f64,
+    pub shield_tilt_level: f64,
+    pub x_mod_x: f64,
+    pub x_mod_y: f64,
+    pub y_mod_x: f64,
+    pub y_mod_y: f64,
+    pub angled_smash_c_y_level: f64,
+    pub light_shield_level: f64,
+    pub analog_shield_levels: [f64; 3],
+    pub shield_slider: ShieldSlider,
+}
+
+impl Default for TechniqueSettings {
+    fn default() -> Self {
+        Self {
+            tilt: Default::default(),
+            shield_tilt: Default::default(),
+            air_dodge: Default::default(),
+            a_stick: Default::default(),
+            b_stick: Default::default(),
+            backdash_out_of_crouch_fix: Default::default(),
+            safe_grounded_down_b: Default::default(),
+            short_hop_release_frame: 1.5,
+            tilt_level: 0.65,
+            shield_tilt_level: 0.6625,
+            x_mod_x: 0.2875,
+            x_mod_y: 0.95,
+            y_mod_x: 0.95,
+            y_mod_y: 0.2875,
+            angled_smash_c_y_level: 0.4,
+            light_shield_level: (43 + 1) as f64 / 255.0,
+            analog_shield_levels: [(43 + 1) as f64 / 255.0, 0.5, 1.0],
+            shield_slider: ShieldSlider::L,
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Copy, Clone)]
+pub struct Bits<const WORDS: usize> {
+    words: [u64; WORDS],
+}
+
+impl<const WORDS: usize> Default for Bits<WORDS> {
+    fn default() -> Self {
+        Self { words: [0; WORDS] }
+    }
+}
+
+impl<const WORDS: usize> Bits<WORDS> {
+    pub fn get(&self, index: usize) -> bool {
+        self.words.get(index / 64).is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+
+    pub fn set(&mut self, index: usize, state: bool) {
+        if let Some(word) = self.words.get_mut(index / 64) {
+            if state {
+                *word |= 1 << (index % 64);
+            }
+            else {
+                *word &= !(1 << (index % 64));
+            }
+        }
+    }
+}
+
+// A fixed size set of flags that can be shared between threads without locking.
+pub struct AtomicBits<const WORDS: usize> {
+    words: [AtomicU64; WORDS],
+}
+
+impl<const WORDS: usize> AtomicBits<WORDS> {
+    pub const fn new() -> Self {
+        Self { words: [const { AtomicU64::new(0) }; WORDS] }
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        self.words.get(index / 64).is_some_and(|word| word.load(Ordering::Acquire) & (1 << (index % 64)) != 0)
+    }
+
+    pub fn set(&self, index: usize, state: bool) {
+        if let Some(word) = self.words.get(index / 64) {
+            if state {
+                word.fetch_or(1 << (index % 64), Ordering::AcqRel);
+            }
+            else {
+                word.fetch_and(!(1 << (index % 64)), Ordering::AcqRel);
+            }
+        }
+    }
+
+    pub fn set_all(&self, state: bool) {
+        let value = if state { u64::MAX } else { 0 };
+        for word in &self.words {
+            word.store(value, Ordering::Release);
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.words.iter().any(|word| word.load(Ordering::Acquire) != 0)
+    }
+
+    pub fn load(&self) -> Bits<WORDS> {
+        let mut bits = Bits::default();
+        for (bits_word, word) in bits.words.iter_mut().zip(self.words.iter()) {
+            *bits_word = word.load(Ordering::Acquire);
+        }
+        bits
+    }
+}
@@ -0,0 +1,300 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::os::raw::{c_int, c_short, c_ulong};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{KeyboardKey, KeyboardDevice, handle_key, any_key_is_blocked, is_paused, print_message};
+use crate::input_backend::{InputBackendHealth, set_input_backend_health, stop_requested};
+use crate::keyboard_device::is_selected;
+
+const INPUT_DIRECTORY: &str = "/dev/input";
+const RESCAN_INTERVAL: Duration = Duration::from_millis(1000);
+const POLL_TIMEOUT_MS: c_int = 100;
+
+const O_NONBLOCK: c_int = 0o4000;
+const POLLIN: c_short = 0x1;
+const POLLERR: c_short = 0x8;
+const POLLHUP: c_short = 0x10;
+const POLLNVAL: c_short = 0x20;
+
+// _IOW('E', 0x90, int)
+const EVIOCGRAB: c_ulong = 0x40044590;
+
+const EV_KEY: u16 = 0x01;
+const KEY_A: usize = 30;
+const KEY_SPACE: usize = 57;
+// Pause has no single scan code to go by.
+const KEY_PAUSE: u16 = 119;
+
+// struct input_event is a timeval followed by type, code and value.
+const TIMEVAL_SIZE: usize = 2 * std::mem::size_of::<usize>();
+const INPUT_EVENT_SIZE: usize = TIMEVAL_SIZE + 8;
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+extern "C" {
+    fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+struct Device {
+    info: KeyboardDevice,
+    file: File,
+    is_grabbed: bool,
+    // Keys pressed on this keyboard and not yet released, as given to handle_key.
+    held_keys: Vec<(KeyboardKey, u16)>,
+}
+
+impl Device {
+    fn set_grabbed(&mut self, state: bool) {
+        if state == self.is_grabbed {
+            return;
+        }
+        let result = unsafe { ioctl(self.file.as_raw_fd(), EVIOCGRAB, state as c_int) };
+        if result == 0 {
+            self.is_grabbed = state;
+        }
+        else if state {
+            print_message(format!("Could not grab {}, its keys will still reach other programs.", self.info.path));
+        }
+    }
+}
+
+// Individual keys can't be kept from other programs here, only whole devices.
+// A keyboard that was picked with set_keyboard_device is grabbed while any key
+// is blocked, which dedicates it to the controller. Keyboards are never grabbed
+// when all of them are being read, since that would take away the main keyboard.
+pub(crate) fn run() {
+    let mut devices: Vec<Device> = Vec::new();
+    let mut failed_paths: Vec<PathBuf> = Vec::new();
+    let mut time_of_last_scan: Option<Instant> = None;
+
+    while !stop_requested() {
+        if time_of_last_scan.is_none_or(|time| time.elapsed() >= RESCAN_INTERVAL) {
+            time_of_last_scan = Some(Instant::now());
+            close_unselected_keyboards(&mut devices);
+            open_new_keyboards(&mut devices, &mut failed_paths);
+        }
+
+        let should_grab = crate::keyboard_device().is_some() && any_key_is_blocked() && !is_paused();
+        for device in &mut devices {
+            device.set_grabbed(should_grab);
+        }
+
+        if devices.is_empty() {
+            thread::sleep(Duration::from_millis(POLL_TIMEOUT_MS as u64));
+            continue;
+        }
+
+        let mut poll_fds: Vec<PollFd> = devices.iter()
+            .map(|device| PollFd { fd: device.file.as_raw_fd(), events: POLLIN, revents: 0 })
+            .collect();
+        let ready_count = unsafe { poll(poll_fds.as_mut_ptr(), poll_fds.len() as c_ulong, POLL_TIMEOUT_MS) };
+        if ready_count <= 0 {
+            continue;
+        }
+
+        let mut lost_keys = Vec::new();
+        let mut lost_device = false;
+        let mut index = 0;
+        devices.retain_mut(|device| {
+            let revents = poll_fds[index].revents;
+            index += 1;
+            let is_connected = revents & (POLLERR | POLLHUP | POLLNVAL) == 0
+                            && (revents & POLLIN == 0 || read_events(device).is_ok());
+            if !is_connected {
+                print_message(format!("Lost keyboard {} ({}).", device.info.name, device.info.path));
+                lost_keys.append(&mut device.held_keys);
+                lost_device = true;
+            }
+            is_connected
+        });
+
+        if lost_device {
+            release_lost_keys(&lost_keys, &devices);
+            if devices.is_empty() {
+                print_message("No keyboards left, waiting for one to be plugged in...".to_string());
+                set_input_backend_health(InputBackendHealth::Disconnected);
+            }
+        }
+    }
+}
+
+// The thread notices the stop request on its next poll timeout.
+pub(crate) fn wake() {}
+
+pub(crate) fn keyboard_devices() -> Vec<KeyboardDevice> {
+    let entries = match fs::read_dir(INPUT_DIRECTORY) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut devices: Vec<KeyboardDevice> = entries.flatten()
+        .filter_map(|entry| keyboard_device_info(&entry.path()))
+        .collect();
+    devices.sort_by(|a, b| a.path.cmp(&b.path));
+    devices
+}
+
+// Releases are never seen for keys held on a keyboard that goes away. Keys that
+// another keyboard is still holding are left pressed.
+fn release_lost_keys(lost_keys: &[(KeyboardKey, u16)], devices: &[Device]) {
+    for &(key, scan_code) in lost_keys {
+        if !devices.iter().any(|device| device.held_keys.contains(&(key, scan_code))) {
+            handle_key(key, scan_code, false);
+        }
+    }
+}
+
+fn close_unselected_keyboards(devices: &mut Vec<Device>) {
+    let (selected, unselected): (Vec<Device>, Vec<Device>) = devices.drain(..).partition(|device| is_selected(&device.info));
+    *devices = selected;
+    if !unselected.is_empty() {
+        let lost_keys: Vec<(KeyboardKey, u16)> = unselected.iter().flat_map(|device| device.held_keys.iter().copied()).collect();
+        release_lost_keys(&lost_keys, devices);
+        if devices.is_empty() {
+            set_input_backend_health(InputBackendHealth::Disconnected);
+        }
+    }
+}
+
+fn open_new_keyboards(devices: &mut Vec<Device>, failed_paths: &mut Vec<PathBuf>) {
+    let was_empty = devices.is_empty();
+    for info in keyboard_devices() {
+        if devices.iter().any(|device| device.info.path == info.path) || !is_selected(&info) {
+            continue;
+        }
+        let path = PathBuf::from(&info.path);
+        match OpenOptions::new().read(true).custom_flags(O_NONBLOCK).open(&path) {
+            Ok(file) => {
+                print_message(format!("Reading keys from {} ({}).", info.name, info.path));
+                failed_paths.retain(|failed_path| *failed_path != path);
+                devices.push(Device { info, file, is_grabbed: false, held_keys: Vec::new() });
+            },
+            Err(e) => {
+                if !failed_paths.contains(&path) {
+                    print_message(format!("Could not open {} ({}). Is your user in the input group?", info.path, e));
+                    failed_paths.push(path);
+                }
+            },
+        }
+    }
+
+    if was_empty && !devices.is_empty() {
+        set_input_backend_health(InputBackendHealth::Installed);
+    }
+}
+
+fn keyboard_device_info(path: &Path) -> Option<KeyboardDevice> {
+    let name = path.file_name()?.to_str()?;
+    if !name.starts_with("event") {
+        return None;
+    }
+    let sys_path = format!("/sys/class/input/{}/device", name);
+    if !is_keyboard(&sys_path) {
+        return None;
+    }
+    let read = |file: &str| fs::read_to_string(format!("{}/{}", sys_path, file))
+        .map(|text| text.trim().to_string())
+        .unwrap_or_default();
+    let read_id = |file: &str| u16::from_str_radix(&read(file), 16).unwrap_or(0);
+    Some(KeyboardDevice {
+        name: read("name"),
+        path: path.to_string_lossy().into_owned(),
+        physical_path: read("phys"),
+        vendor_id: read_id("id/vendor"),
+        product_id: read_id("id/product"),
+    })
+}
+
+// A keyboard is anything that has at least the A key and the space bar.
+fn is_keyboard(sys_path: &str) -> bool {
+    let capabilities = match fs::read_to_string(format!("{}/capabilities/key", sys_path)) {
+        Ok(capabilities) => capabilities,
+        Err(_) => return false,
+    };
+    // The words are written in hex, most significant first, one unsigned long each.
+    let words: Vec<&str> = capabilities.split_whitespace().collect();
+    let has_key = |key: usize| {
+        let word_bits = 8 * std::mem::size_of::<c_ulong>();
+        let word_index = key / word_bits;
+        word_index < words.len()
+        && u64::from_str_radix(words[words.len() - 1 - word_index], 16)
+            .is_ok_and(|word| word & (1 << (key % word_bits)) != 0)
+    };
+    has_key(KEY_A) && has_key(KEY_SPACE)
+}
+
+fn read_events(device: &mut Device) -> io::Result<()> {
+    let mut buffer = [0u8; INPUT_EVENT_SIZE * 64];
+    loop {
+        let byte_count = match device.file.read(&mut buffer) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(byte_count) => byte_count,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for event in buffer[..byte_count].chunks_exact(INPUT_EVENT_SIZE) {
+            let event_type = u16::from_ne_bytes([event[TIMEVAL_SIZE], event[TIMEVAL_SIZE + 1]]);
+            let code = u16::from_ne_bytes([event[TIMEVAL_SIZE + 2], event[TIMEVAL_SIZE + 3]]);
+            let value = i32::from_ne_bytes([
+                event[TIMEVAL_SIZE + 4], event[TIMEVAL_SIZE + 5],
+                event[TIMEVAL_SIZE + 6], event[TIMEVAL_SIZE + 7],
+            ]);
+            if event_type != EV_KEY {
+                continue;
+            }
+            // A value of 2 is an auto repeat, which is treated like any other held key.
+            let (key, scan_code) = if code == KEY_PAUSE {
+                (KeyboardKey::Pause, 0)
+            }
+            else if let Some(scan_code) = scan_code_from_evdev_code(code) {
+                (KeyboardKey::from_us_layout_scan_code(scan_code).unwrap_or(KeyboardKey::Unknown(0)), scan_code)
+            }
+            else {
+                continue;
+            };
+            let is_pressed = value != 0;
+            device.held_keys.retain(|&held_key| held_key != (key, scan_code));
+            if is_pressed {
+                device.held_keys.push((key, scan_code));
+            }
+            handle_key(key, scan_code, is_pressed);
+        }
+    }
+}
+
+// Evdev codes below 89 are the same as set 1 scan codes.
+fn scan_code_from_evdev_code(code: u16) -> Option<u16> {
+    match code {
+        1..=83 | 86..=88 => Some(code),
+        96 => Some(0xE01C),
+        97 => Some(0xE01D),
+        98 => Some(0xE035),
+        99 => Some(0xE037),
+        100 => Some(0xE038),
+        102 => Some(0xE047),
+        103 => Some(0xE048),
+        104 => Some(0xE049),
+        105 => Some(0xE04B),
+        106 => Some(0xE04D),
+        107 => Some(0xE04F),
+        108 => Some(0xE050),
+        109 => Some(0xE051),
+        110 => Some(0xE052),
+        111 => Some(0xE053),
+        125 => Some(0xE05B),
+        126 => Some(0xE05C),
+        127 => Some(0xE05D),
+        _ => None,
+    }
+}
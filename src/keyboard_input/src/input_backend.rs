@@ -0,0 +1,77 @@
+use std::thread::{self, JoinHandle};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+#[cfg(windows)]
+use crate::windows_hook as backend;
+#[cfg(target_os = "linux")]
+use crate::evdev as backend;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputBackendHealth {
+    // start_hook hasn't been called, or stop_hook has.
+    Stopped,
+    // The backend is listening but hasn't seen a key yet.
+    Installed,
+    ReceivingEvents,
+    // The event source is gone. The backend keeps trying to get it back.
+    Disconnected,
+}
+
+impl InputBackendHealth {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => InputBackendHealth::Installed,
+            2 => InputBackendHealth::ReceivingEvents,
+            3 => InputBackendHealth::Disconnected,
+            _ => InputBackendHealth::Stopped,
+        }
+    }
+}
+
+static HEALTH: AtomicU8 = AtomicU8::new(InputBackendHealth::Stopped as u8);
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref BACKEND_THREAD: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+}
+
+pub fn input_backend_health() -> InputBackendHealth {
+    InputBackendHealth::from_u8(HEALTH.load(Ordering::Acquire))
+}
+
+pub(crate) fn set_input_backend_health(health: InputBackendHealth) {
+    HEALTH.store(health as u8, Ordering::Release);
+}
+
+pub(crate) fn report_event_received() {
+    let _ = HEALTH.compare_exchange(
+        InputBackendHealth::Installed as u8,
+        InputBackendHealth::ReceivingEvents as u8,
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    );
+}
+
+pub(crate) fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::Acquire)
+}
+
+pub fn start_hook() {
+    stop_hook();
+    STOP_REQUESTED.store(false, Ordering::Release);
+    set_input_backend_health(InputBackendHealth::Disconnected);
+    *BACKEND_THREAD.lock().unwrap() = Some(thread::spawn(backend::run));
+}
+
+// Blocks until the backend has let go of its event source.
+pub fn stop_hook() {
+    let thread = BACKEND_THREAD.lock().unwrap().take();
+    if let Some(thread) = thread {
+        STOP_REQUESTED.store(true, Ordering::Release);
+        backend::wake();
+        let _ = thread.join();
+        crate::release_all_keys();
+    }
+    set_input_backend_health(InputBackendHealth::Stopped);
+}
@@ -0,0 +1,155 @@
+use serde::{Serialize, Deserialize};
+
+use crate::KeyboardKey;
+
+// Extended scan codes are written with the 0xE000 prefix, e.g. 0xE038 is right alt.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PhysicalKey {
+    ScanCode(u16),
+}
+
+// A bind is either a virtual key, which follows the keyboard layout,
+// or a physical key, which stays in the same place on every layout.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyBind {
+    Key(KeyboardKey),
+    Physical(PhysicalKey),
+}
+
+impl From<KeyboardKey> for KeyBind {
+    fn from(key: KeyboardKey) -> Self { KeyBind::Key(key) }
+}
+
+impl KeyBind {
+    // Binds that were made with a US QWERTY layout in mind are moved to the
+    // physical key they sit on there, so they end up in the same place on any layout.
+    pub fn to_physical(self) -> KeyBind {
+        match self {
+            KeyBind::Key(key) => match key.us_layout_scan_code() {
+                Some(scan_code) => KeyBind::Physical(PhysicalKey::ScanCode(scan_code)),
+                None => self,
+            },
+            KeyBind::Physical(_) => self,
+        }
+    }
+}
+
+impl KeyboardKey {
+    pub fn us_layout_scan_code(&self) -> Option<u16> {
+        match self {
+            KeyboardKey::Escape => Some(0x01),
+            KeyboardKey::Key1 => Some(0x02),
+            KeyboardKey::Key2 => Some(0x03),
+            KeyboardKey::Key3 => Some(0x04),
+            KeyboardKey::Key4 => Some(0x05),
+            KeyboardKey::Key5 => Some(0x06),
+            KeyboardKey::Key6 => Some(0x07),
+            KeyboardKey::Key7 => Some(0x08),
+            KeyboardKey::Key8 => Some(0x09),
+            KeyboardKey::Key9 => Some(0x0A),
+            KeyboardKey::Key0 => Some(0x0B),
+            KeyboardKey::Minus => Some(0x0C),
+            KeyboardKey::Equals => Some(0x0D),
+            KeyboardKey::Backspace => Some(0x0E),
+            KeyboardKey::Tab => Some(0x0F),
+            KeyboardKey::Q => Some(0x10),
+            KeyboardKey::W => Some(0x11),
+            KeyboardKey::E => Some(0x12),
+            KeyboardKey::R => Some(0x13),
+            KeyboardKey::T => Some(0x14),
+            KeyboardKey::Y => Some(0x15),
+            KeyboardKey::U => Some(0x16),
+            KeyboardKey::I => Some(0x17),
+            KeyboardKey::O => Some(0x18),
+            KeyboardKey::P => Some(0x19),
+            KeyboardKey::LeftBracket => Some(0x1A),
+            KeyboardKey::RightBracket => Some(0x1B),
+            KeyboardKey::Enter => Some(0x1C),
+            KeyboardKey::Control | KeyboardKey::LeftControl => Some(0x1D),
+            KeyboardKey::A => Some(0x1E),
+            KeyboardKey::S => Some(0x1F),
+            KeyboardKey::D => Some(0x20),
+            KeyboardKey::F => Some(0x21),
+            KeyboardKey::G => Some(0x22),
+            KeyboardKey::H => Some(0x23),
+            KeyboardKey::J => Some(0x24),
+            KeyboardKey::K => Some(0x25),
+            KeyboardKey::L => Some(0x26),
+            KeyboardKey::Semicolon => Some(0x27),
+            KeyboardKey::Apostrophe => Some(0x28),
+            KeyboardKey::Grave => Some(0x29),
+            KeyboardKey::Shift | KeyboardKey::LeftShift => Some(0x2A),
+            KeyboardKey::BackSlash => Some(0x2B),
+            KeyboardKey::Z => Some(0x2C),
+            KeyboardKey::X => Some(0x2D),
+            KeyboardKey::C => Some(0x2E),
+            KeyboardKey::V => Some(0x2F),
+            KeyboardKey::B => Some(0x30),
+            KeyboardKey::N => Some(0x31),
+            KeyboardKey::M => Some(0x32),
+            KeyboardKey::Comma => Some(0x33),
+            KeyboardKey::Period => Some(0x34),
+            KeyboardKey::Slash => Some(0x35),
+            KeyboardKey::RightShift => Some(0x36),
+            KeyboardKey::NumPadMultiply => Some(0x37),
+            KeyboardKey::Alt | KeyboardKey::LeftAlt => Some(0x38),
+            KeyboardKey::Space => Some(0x39),
+            KeyboardKey::CapsLock => Some(0x3A),
+            KeyboardKey::F1 => Some(0x3B),
+            KeyboardKey::F2 => Some(0x3C),
+            KeyboardKey::F3 => Some(0x3D),
+            KeyboardKey::F4 => Some(0x3E),
+            KeyboardKey::F5 => Some(0x3F),
+            KeyboardKey::F6 => Some(0x40),
+            KeyboardKey::F7 => Some(0x41),
+            KeyboardKey::F8 => Some(0x42),
+            KeyboardKey::F9 => Some(0x43),
+            KeyboardKey::F10 => Some(0x44),
+            KeyboardKey::NumLock => Some(0x45),
+            KeyboardKey::ScrollLock => Some(0x46),
+            KeyboardKey::NumPad7 => Some(0x47),
+            KeyboardKey::NumPad8 => Some(0x48),
+            KeyboardKey::NumPad9 => Some(0x49),
+            KeyboardKey::NumPadSubtract => Some(0x4A),
+            KeyboardKey::NumPad4 => Some(0x4B),
+            KeyboardKey::NumPad5 => Some(0x4C),
+            KeyboardKey::NumPad6 => Some(0x4D),
+            KeyboardKey::NumPadAdd => Some(0x4E),
+            KeyboardKey::NumPad1 => Some(0x4F),
+            KeyboardKey::NumPad2 => Some(0x50),
+            KeyboardKey::NumPad3 => Some(0x51),
+            KeyboardKey::NumPad0 => Some(0x52),
+            KeyboardKey::NumPadDecimal => Some(0x53),
+            KeyboardKey::Oem102 => Some(0x56),
+            KeyboardKey::F11 => Some(0x57),
+            KeyboardKey::F12 => Some(0x58),
+            KeyboardKey::RightControl => Some(0xE01D),
+            KeyboardKey::NumPadDivide => Some(0xE035),
+            KeyboardKey::PrintScreen => Some(0xE037),
+            KeyboardKey::RightAlt => Some(0xE038),
+            KeyboardKey::Home => Some(0xE047),
+            KeyboardKey::UpArrow => Some(0xE048),
+            KeyboardKey::PageUp => Some(0xE049),
+            KeyboardKey::LeftArrow => Some(0xE04B),
+            KeyboardKey::RightArrow => Some(0xE04D),
+            KeyboardKey::End => Some(0xE04F),
+            KeyboardKey::DownArrow => Some(0xE050),
+            KeyboardKey::PageDown => Some(0xE051),
+            KeyboardKey::Insert => Some(0xE052),
+            KeyboardKey::Delete => Some(0xE053),
+            KeyboardKey::LeftWindows => Some(0xE05B),
+            KeyboardKey::RightWindows => Some(0xE05C),
+            KeyboardKey::Applications => Some(0xE05D),
+            _ => None,
+        }
+    }
+
+    // Left and right versions of modifiers are preferred over the generic ones,
+    // since those are what a keyboard actually reports.
+    pub fn from_us_layout_scan_code(scan_code: u16) -> Option<KeyboardKey> {
+        (0..crate::KEY_COUNT).rev()
+            .map(KeyboardKey::from_keycode)
+            .find(|key| key.us_layout_scan_code() == Some(scan_code))
+    }
+}
@@ -0,0 +1,29 @@
+use std::time::Instant;
+
+use crossbeam_queue::ArrayQueue;
+
+use crate::KeyboardKey;
+
+const KEY_EVENT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: KeyboardKey,
+    pub scan_code: u16,
+    pub is_pressed: bool,
+    pub time: Instant,
+}
+
+lazy_static! {
+    static ref KEY_EVENTS: ArrayQueue<KeyEvent> = ArrayQueue::new(KEY_EVENT_CAPACITY);
+}
+
+// Called from the hook, so this must never block. If nobody is draining
+// the queue the oldest events are thrown away.
+pub(crate) fn push_key_event(event: KeyEvent) {
+    KEY_EVENTS.force_push(event);
+}
+
+pub fn pop_key_event() -> Option<KeyEvent> {
+    KEY_EVENTS.pop()
+}
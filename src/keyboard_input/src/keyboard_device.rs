@@ -0,0 +1,83 @@
+use std::sync::Mutex;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyboardDevice {
+    pub name: String,
+    // The event node, e.g. /dev/input/event3, or on Windows the device
+    // interface name, e.g. \\?\HID#VID_046D&PID_C31C&MI_00#...
+    pub path: String,
+    // Where the device is plugged in, e.g. usb-0000:00:14.0-2/input0. Empty on Windows.
+    pub physical_path: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+}
+
+// Picks out one keyboard so it can be dedicated to the controller.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyboardDeviceSelector {
+    Name(String),
+    // Either the event node, a symlink to it such as /dev/input/by-id/..., or the physical path.
+    // On Windows, the device interface name.
+    Path(String),
+    // Vendor and product id in hex, the way lsusb shows them, e.g. "046d:c31c".
+    VendorProductId(String),
+}
+
+impl KeyboardDeviceSelector {
+    pub fn matches(&self, device: &KeyboardDevice) -> bool {
+        match self {
+            KeyboardDeviceSelector::Name(name) => device.name == *name,
+            KeyboardDeviceSelector::Path(path) => {
+                device.path == *path
+                || device.physical_path == *path
+                || Path::new(path).canonicalize().is_ok_and(|path| path == Path::new(&device.path))
+            },
+            KeyboardDeviceSelector::VendorProductId(id) => {
+                id.eq_ignore_ascii_case(&format!("{:04x}:{:04x}", device.vendor_id, device.product_id))
+            },
+        }
+    }
+}
+
+lazy_static! {
+    static ref KEYBOARD_DEVICE_SELECTOR: Mutex<Option<KeyboardDeviceSelector>> = Mutex::new(None);
+}
+
+pub fn supports_keyboard_device_selection() -> bool {
+    cfg!(any(target_os = "linux", windows))
+}
+
+#[cfg(target_os = "linux")]
+pub fn keyboard_devices() -> Vec<KeyboardDevice> {
+    crate::evdev::keyboard_devices()
+}
+
+#[cfg(windows)]
+pub fn keyboard_devices() -> Vec<KeyboardDevice> {
+    crate::raw_input::keyboard_devices()
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn keyboard_devices() -> Vec<KeyboardDevice> {
+    Vec::new()
+}
+
+// With no selector every keyboard is read. Takes effect the next time the
+// backend looks for devices, so it can be changed while running.
+pub fn set_keyboard_device(selector: Option<KeyboardDeviceSelector>) {
+    *KEYBOARD_DEVICE_SELECTOR.lock().unwrap() = selector;
+}
+
+pub fn keyboard_device() -> Option<KeyboardDeviceSelector> {
+    KEYBOARD_DEVICE_SELECTOR.lock().unwrap().clone()
+}
+
+#[cfg(any(target_os = "linux", windows))]
+pub(crate) fn is_selected(device: &KeyboardDevice) -> bool {
+    KEYBOARD_DEVICE_SELECTOR.lock().unwrap()
+        .as_ref()
+        .is_none_or(|selector| selector.matches(device))
+}
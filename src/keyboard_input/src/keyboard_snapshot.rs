@@ -0,0 +1,31 @@
+use crate::{KeyboardKey, KeyBind, PhysicalKey, KeyEvent, KEY_WORDS, SCAN_CODE_WORDS, scan_code_index};
+use crate::atomic_bits::Bits;
+
+// The pressed state of every key at one point in time.
+#[derive(Copy, Clone, Default)]
+pub struct KeyboardSnapshot {
+    pub(crate) keys: Bits<KEY_WORDS>,
+    pub(crate) scan_codes: Bits<SCAN_CODE_WORDS>,
+}
+
+impl KeyboardSnapshot {
+    pub fn apply(&mut self, event: &KeyEvent) {
+        self.keys.set(event.key.to_keycode(), event.is_pressed);
+        self.scan_codes.set(scan_code_index(event.scan_code), event.is_pressed);
+    }
+
+    pub fn key_is_pressed(&self, key: KeyboardKey) -> bool {
+        self.keys.get(key.to_keycode())
+    }
+
+    pub fn scan_code_is_pressed(&self, scan_code: u16) -> bool {
+        self.scan_codes.get(scan_code_index(scan_code))
+    }
+
+    pub fn key_bind_is_pressed(&self, key_bind: KeyBind) -> bool {
+        match key_bind {
+            KeyBind::Key(key) => self.key_is_pressed(key),
+            KeyBind::Physical(PhysicalKey::ScanCode(scan_code)) => self.scan_code_is_pressed(scan_code),
+        }
+    }
+}
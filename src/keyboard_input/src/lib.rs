@@ -1,144 +1,249 @@
 #[macro_use]
 extern crate lazy_static;
 
+mod atomic_bits;
+mod input_backend;
+mod key_bind;
+mod key_event;
+mod keyboard_device;
+mod keyboard_snapshot;
+mod messages;
+#[cfg(windows)]
+mod windows_hook;
+#[cfg(windows)]
+mod raw_input;
+#[cfg(target_os = "linux")]
+mod evdev;
+
 use serde::{Serialize, Deserialize};
 
-use std::thread;
-use std::ptr::null_mut;
-use std::sync::Mutex;
-use winapi::{
-    ctypes::*,
-    shared::{minwindef::*},
-    shared::{windef::*},
-    um::winuser::*,
+use std::time::Instant;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+pub use crate::input_backend::{InputBackendHealth, input_backend_health, start_hook, stop_hook};
+pub use crate::key_bind::{KeyBind, PhysicalKey};
+pub use crate::key_event::{KeyEvent, pop_key_event};
+pub use crate::keyboard_device::{
+    KeyboardDevice,
+    KeyboardDeviceSelector,
+    supports_keyboard_device_selection,
+    keyboard_devices,
+    set_keyboard_device,
+    keyboard_device,
 };
+pub use crate::keyboard_snapshot::KeyboardSnapshot;
+pub use crate::messages::{print_message, hold_messages, release_messages, take_held_messages};
 
-pub fn start_hook() {
-    thread::spawn(move || {
-        unsafe {
-            SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_hook), 0 as HINSTANCE, 0);
-            loop {
-                let mut msg: MSG = MSG {
-                    hwnd: null_mut(),
-                    message: 0,
-                    wParam: 0,
-                    lParam: 0,
-                    time: 0,
-                    pt: POINT { x: 0, y: 0 },
-                };
-                let msg: LPMSG = &mut msg;
-                GetMessageA(msg, null_mut(), 0, 0);
-                TranslateMessage(msg);
-                DispatchMessageA(msg);
-            }
-        }
-    });
-}
+use crate::atomic_bits::AtomicBits;
 
 pub fn key_is_pressed(key: KeyboardKey) -> bool {
-    KEYBOARD_STATE.lock().unwrap().key_is_pressed(key)
+    KEYBOARD_STATE.pressed_keys.get(key.to_keycode())
+}
+
+pub fn scan_code_is_pressed(scan_code: u16) -> bool {
+    KEYBOARD_STATE.pressed_scan_codes.get(scan_code_index(scan_code))
+}
+
+pub fn key_bind_is_pressed(key_bind: KeyBind) -> bool {
+    match key_bind {
+        KeyBind::Key(key) => key_is_pressed(key),
+        KeyBind::Physical(PhysicalKey::ScanCode(scan_code)) => scan_code_is_pressed(scan_code),
+    }
+}
+
+pub fn keyboard_snapshot() -> KeyboardSnapshot {
+    KeyboardSnapshot {
+        keys: KEYBOARD_STATE.pressed_keys.load(),
+        scan_codes: KEYBOARD_STATE.pressed_scan_codes.load(),
+    }
+}
+
+// The pause hotkey is handled inside the hook so it works while every other key is blocked.
+// While paused no keys are blocked.
+pub fn set_pause_hotkey(key_bind: Option<KeyBind>) {
+    KEYBOARD_STATE.pause_hotkey.store(encode_key_bind(key_bind), Ordering::Release);
+}
+
+pub fn is_paused() -> bool {
+    KEYBOARD_STATE.is_paused.load(Ordering::Acquire)
+}
+
+pub fn set_paused(state: bool) {
+    KEYBOARD_STATE.is_paused.store(state, Ordering::Release);
 }
 
 pub fn key_is_blocked(key: KeyboardKey) -> bool {
-    KEYBOARD_STATE.lock().unwrap().key_is_blocked(key)
+    KEYBOARD_STATE.blocked_keys.get(key.to_keycode())
 }
 
 pub fn set_key_blocked(key: KeyboardKey, state: bool) {
-    KEYBOARD_STATE.lock().unwrap().set_key_blocked(key, state);
+    set_key_bind_blocked(KeyBind::Key(key), state);
+}
+
+pub fn set_key_bind_blocked(key_bind: KeyBind, state: bool) {
+    match key_bind {
+        KeyBind::Key(key) => KEYBOARD_STATE.blocked_keys.set(key.to_keycode(), state),
+        KeyBind::Physical(PhysicalKey::ScanCode(scan_code)) => KEYBOARD_STATE.blocked_scan_codes.set(scan_code_index(scan_code), state),
+    }
+}
+
+pub fn set_key_bind_passthrough(key_bind: KeyBind, state: bool) {
+    match key_bind {
+        KeyBind::Key(key) => KEYBOARD_STATE.passthrough_keys.set(key.to_keycode(), state),
+        KeyBind::Physical(PhysicalKey::ScanCode(scan_code)) => KEYBOARD_STATE.passthrough_scan_codes.set(scan_code_index(scan_code), state),
+    }
+}
+
+pub fn clear_passthrough_keys() {
+    KEYBOARD_STATE.passthrough_keys.set_all(false);
+    KEYBOARD_STATE.passthrough_scan_codes.set_all(false);
 }
 
 pub fn set_all_keys_blocked(state: bool) {
-    KEYBOARD_STATE.lock().unwrap().set_all_keys_blocked(state);
+    KEYBOARD_STATE.blocked_keys.set_all(state);
+    KEYBOARD_STATE.blocked_scan_codes.set_all(state);
+}
+
+pub fn any_key_is_blocked() -> bool {
+    KEYBOARD_STATE.blocked_keys.any() || KEYBOARD_STATE.blocked_scan_codes.any()
 }
 
 pub fn block_key(key: KeyboardKey) {
-    KEYBOARD_STATE.lock().unwrap().set_key_blocked(key, true);
+    set_key_blocked(key, true);
 }
 
 pub fn block_all_keys() {
-    KEYBOARD_STATE.lock().unwrap().set_all_keys_blocked(true);
+    set_all_keys_blocked(true);
 }
 
 pub fn unblock_key(key: KeyboardKey) {
-    KEYBOARD_STATE.lock().unwrap().set_key_blocked(key, false);
+    set_key_blocked(key, false);
 }
 
 pub fn unblock_all_keys() {
-    KEYBOARD_STATE.lock().unwrap().set_all_keys_blocked(false);
+    set_all_keys_blocked(false);
+}
+
+const KEY_COUNT: usize = 256;
+const KEY_WORDS: usize = KEY_COUNT / 64;
+
+// Extended scan codes get their own half of the table.
+const SCAN_CODE_COUNT: usize = 512;
+const SCAN_CODE_WORDS: usize = SCAN_CODE_COUNT / 64;
+
+fn scan_code_index(scan_code: u16) -> usize {
+    let extended_offset = if scan_code & 0xFF00 == 0xE000 { 256 } else { 0 };
+    (scan_code & 0xFF) as usize + extended_offset
+}
+
+fn scan_code_from_index(index: usize) -> u16 {
+    if index >= 256 { 0xE000 | (index - 256) as u16 } else { index as u16 }
+}
+
+const NO_KEY_BIND: u32 = 0;
+const KEY_BIND_KEY_FLAG: u32 = 1 << 16;
+const KEY_BIND_SCAN_CODE_FLAG: u32 = 1 << 17;
+
+// Packs a key bind into a u32 so it can live in an atomic.
+fn encode_key_bind(key_bind: Option<KeyBind>) -> u32 {
+    match key_bind {
+        Some(KeyBind::Key(key)) => KEY_BIND_KEY_FLAG | (key.to_keycode() as u32 & 0xFFFF),
+        Some(KeyBind::Physical(PhysicalKey::ScanCode(scan_code))) => KEY_BIND_SCAN_CODE_FLAG | scan_code as u32,
+        None => NO_KEY_BIND,
+    }
+}
+
+// Everything the hook touches is atomic, so the hook never waits on the main loop.
+struct KeyboardState {
+    pressed_keys: AtomicBits<KEY_WORDS>,
+    pressed_scan_codes: AtomicBits<SCAN_CODE_WORDS>,
+    blocked_keys: AtomicBits<KEY_WORDS>,
+    blocked_scan_codes: AtomicBits<SCAN_CODE_WORDS>,
+    passthrough_keys: AtomicBits<KEY_WORDS>,
+    passthrough_scan_codes: AtomicBits<SCAN_CODE_WORDS>,
+    pause_hotkey: AtomicU32,
+    is_paused: AtomicBool,
 }
 
-#[derive(Default)]
-struct KeyState {
-    is_pressed: bool,
-    is_blocked: bool
+impl KeyboardState {
+    const fn new() -> Self {
+        Self {
+            pressed_keys: AtomicBits::new(),
+            pressed_scan_codes: AtomicBits::new(),
+            blocked_keys: AtomicBits::new(),
+            blocked_scan_codes: AtomicBits::new(),
+            passthrough_keys: AtomicBits::new(),
+            passthrough_scan_codes: AtomicBits::new(),
+            pause_hotkey: AtomicU32::new(NO_KEY_BIND),
+            is_paused: AtomicBool::new(false),
+        }
+    }
+
+    fn is_pause_hotkey(&self, key: KeyboardKey, scan_code: u16) -> bool {
+        let pause_hotkey = self.pause_hotkey.load(Ordering::Acquire);
+        pause_hotkey != NO_KEY_BIND
+        && (pause_hotkey == encode_key_bind(Some(KeyBind::Key(key)))
+         || pause_hotkey == encode_key_bind(Some(KeyBind::Physical(PhysicalKey::ScanCode(scan_code)))))
+    }
+
+    // Passthrough keys are never blocked, no matter how they are blocked.
+    fn should_block(&self, key: KeyboardKey, scan_code: u16) -> bool {
+        let keycode = key.to_keycode();
+        let scan_code = scan_code_index(scan_code);
+        let is_passthrough = self.passthrough_keys.get(keycode) || self.passthrough_scan_codes.get(scan_code);
+        let is_blocked = self.blocked_keys.get(keycode) || self.blocked_scan_codes.get(scan_code);
+        is_blocked && !is_passthrough
+    }
+
+    // Different physical keys can share a key, like Home and Numpad7 with NumLock off,
+    // or every key evdev has no name for. Only the same physical key can repeat.
+    fn is_pressed(&self, key: KeyboardKey, scan_code: u16) -> bool {
+        self.pressed_keys.get(key.to_keycode()) && self.pressed_scan_codes.get(scan_code_index(scan_code))
+    }
+
+    fn set_pressed(&self, key: KeyboardKey, scan_code: u16, state: bool) {
+        self.pressed_keys.set(key.to_keycode(), state);
+        self.pressed_scan_codes.set(scan_code_index(scan_code), state);
+    }
 }
 
+// Keys without a name are still usable as Unknown(keycode).
 macro_rules! keyboard {
     ($($key_name:ident: $keycode:expr),+) => {
         #[allow(dead_code)]
         #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
         pub enum KeyboardKey {
-            $($key_name),+
+            $($key_name,)+
+            Unknown(usize),
         }
 
         impl KeyboardKey {
             #[allow(dead_code)]
             pub fn to_keycode(&self) -> usize {
                 match self {
-                    $(KeyboardKey::$key_name => $keycode),+
+                    $(KeyboardKey::$key_name => $keycode,)+
+                    KeyboardKey::Unknown(keycode) => *keycode,
                 }
             }
 
             #[allow(dead_code)]
             pub fn from_keycode(keycode: usize) -> KeyboardKey {
                 match keycode {
-                    $($keycode => KeyboardKey::$key_name),+
-                    ,
-                    _ => panic!("Tried to convert unknown keycode: {}", keycode),
-                }
-            }
-        }
-
-        #[allow(non_snake_case)]
-        #[derive(Default)]
-        struct KeyboardState {
-            $($key_name: KeyState),+
-        }
-
-        impl KeyboardState {
-            pub fn key_is_pressed(&self, key: KeyboardKey) -> bool {
-                match key {
-                    $(KeyboardKey::$key_name => self.$key_name.is_pressed),+
-                }
-            }
-
-            pub fn key_is_blocked(&mut self, key: KeyboardKey) -> bool {
-                match key {
-                    $(KeyboardKey::$key_name => self.$key_name.is_blocked),+
+                    $($keycode => KeyboardKey::$key_name,)+
+                    _ => KeyboardKey::Unknown(keycode),
                 }
             }
-
-            fn set_key_pressed(&mut self, key: KeyboardKey, state: bool) {
-                match key {
-                    $(KeyboardKey::$key_name => self.$key_name.is_pressed = state),+
-                }
-            }
-
-            fn set_key_blocked(&mut self, key: KeyboardKey, state: bool) {
-                match key {
-                    $(KeyboardKey::$key_name => self.$key_name.is_blocked = state),+
-                }
-            }
-
-            fn set_all_keys_blocked(&mut self, state: bool) {
-                $(self.$key_name.is_blocked = state);+
-            }
         }
     };
 }
 
 keyboard!(
+    LeftMouseButton: 1,
+    RightMouseButton: 2,
     ControlBreak: 3,
+    MiddleMouseButton: 4,
+    XMouseButton1: 5,
+    XMouseButton2: 6,
     Backspace: 8,
     Tab: 9,
     Clear: 12,
@@ -149,9 +254,11 @@ keyboard!(
     Pause: 19,
     CapsLock: 20,
     IMEKana: 21,
+    IMEOn: 22,
     IMEJunja: 23,
     IMEFinal: 24,
     IMEHanja: 25,
+    IMEOff: 26,
     Escape: 27,
     IMEConvert: 28,
     IMENonConvert: 29,
@@ -247,13 +354,28 @@ keyboard!(
     F16: 127,
     F17: 128,
     F18: 129,
-    F20: 130,
-    F21: 131,
-    F22: 132,
-    F23: 133,
-    F24: 134,
+    // These were once 130 to 134 with no F19. Loading an old config moves its binds.
+    F19: 130,
+    F20: 131,
+    F21: 132,
+    F22: 133,
+    F23: 134,
+    F24: 135,
+    NavigationView: 136,
+    NavigationMenu: 137,
+    NavigationUp: 138,
+    NavigationDown: 139,
+    NavigationLeft: 140,
+    NavigationRight: 141,
+    NavigationAccept: 142,
+    NavigationCancel: 143,
     NumLock: 144,
     ScrollLock: 145,
+    OemFjJisho: 146,
+    OemFjMasshou: 147,
+    OemFjTouroku: 148,
+    OemFjLoya: 149,
+    OemFjRoya: 150,
     LeftShift: 160,
     RightShift: 161,
     LeftControl: 162,
@@ -285,38 +407,128 @@ keyboard!(
     Period: 190,
     Slash: 191,
     Grave: 192,
+    AbntC1: 193,
+    AbntC2: 194,
+    GamepadA: 195,
+    GamepadB: 196,
+    GamepadX: 197,
+    GamepadY: 198,
+    GamepadRightShoulder: 199,
+    GamepadLeftShoulder: 200,
+    GamepadLeftTrigger: 201,
+    GamepadRightTrigger: 202,
+    GamepadDPadUp: 203,
+    GamepadDPadDown: 204,
+    GamepadDPadLeft: 205,
+    GamepadDPadRight: 206,
+    GamepadMenu: 207,
+    GamepadView: 208,
+    GamepadLeftThumbstickButton: 209,
+    GamepadRightThumbstickButton: 210,
+    GamepadLeftThumbstickUp: 211,
+    GamepadLeftThumbstickDown: 212,
+    GamepadLeftThumbstickRight: 213,
+    GamepadLeftThumbstickLeft: 214,
+    GamepadRightThumbstickUp: 215,
+    GamepadRightThumbstickDown: 216,
+    GamepadRightThumbstickRight: 217,
+    GamepadRightThumbstickLeft: 218,
     LeftBracket: 219,
     BackSlash: 220,
     RightBracket: 221,
     Apostrophe: 222,
-    IMEProcess: 229
+    Oem8: 223,
+    OemAx: 225,
+    Oem102: 226,
+    IcoHelp: 227,
+    Ico00: 228,
+    IMEProcess: 229,
+    IcoClear: 230,
+    Packet: 231,
+    OemReset: 233,
+    OemJump: 234,
+    OemPa1: 235,
+    OemPa2: 236,
+    OemPa3: 237,
+    OemWsCtrl: 238,
+    OemCuSel: 239,
+    OemAttn: 240,
+    OemFinish: 241,
+    OemCopy: 242,
+    OemAuto: 243,
+    OemEnlw: 244,
+    OemBackTab: 245,
+    Attn: 246,
+    CrSel: 247,
+    ExSel: 248,
+    EraseEof: 249,
+    Play: 250,
+    Zoom: 251,
+    NoName: 252,
+    Pa1: 253,
+    OemClear: 254
 );
 
-lazy_static! {
-    static ref KEYBOARD_STATE: Mutex<KeyboardState> = Mutex::new(KeyboardState::default());
-}
+static KEYBOARD_STATE: KeyboardState = KeyboardState::new();
+
+// Called by the input backend for every key press and release it sees.
+// Returns whether the key should be kept from reaching other programs.
+pub(crate) fn handle_key(key: KeyboardKey, scan_code: u16, is_pressed: bool) -> bool {
+    let time = Instant::now();
+    let keyboard = &KEYBOARD_STATE;
+    let is_pause_hotkey = keyboard.is_pause_hotkey(key, scan_code);
+    let is_repeat = is_pressed && keyboard.is_pressed(key, scan_code);
+    keyboard.set_pressed(key, scan_code, is_pressed);
+    input_backend::report_event_received();
 
-unsafe extern "system" fn keyboard_hook(code: c_int, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
-    let mut block_key_press = false;
-
-    match w_param as u32 {
-        WM_KEYDOWN | WM_SYSKEYDOWN => {
-            let key_code = (*(l_param as *const KBDLLHOOKSTRUCT)).vkCode;
-            let keyboard_key = KeyboardKey::from_keycode(key_code as usize);
-            let mut keyboard = KEYBOARD_STATE.lock().unwrap();
-            keyboard.set_key_pressed(keyboard_key, true);
-            block_key_press = keyboard.key_is_blocked(keyboard_key);
-        },
-        WM_KEYUP | WM_SYSKEYUP => {
-            let key_code = (*(l_param as *const KBDLLHOOKSTRUCT)).vkCode;
-            let keyboard_key = KeyboardKey::from_keycode(key_code as usize);
-            let mut keyboard = KEYBOARD_STATE.lock().unwrap();
-            keyboard.set_key_pressed(keyboard_key, false);
-            block_key_press = keyboard.key_is_blocked(keyboard_key);
-        },
-        _ => ()
+    if !is_repeat {
+        key_event::push_key_event(KeyEvent {
+            key,
+            scan_code,
+            is_pressed,
+            time,
+        });
     }
 
-    if block_key_press { 1 }
-    else { CallNextHookEx(null_mut(), code, w_param, l_param) }
+    if is_pause_hotkey {
+        if !is_repeat && is_pressed {
+            keyboard.is_paused.fetch_xor(true, Ordering::AcqRel);
+        }
+        true
+    }
+    else if !keyboard.is_paused.load(Ordering::Acquire) {
+        keyboard.should_block(key, scan_code)
+    }
+    else {
+        false
+    }
+}
+
+// Releases are never seen for keys that are held while the backend goes away,
+// so they are released here instead.
+pub(crate) fn release_all_keys() {
+    let time = Instant::now();
+    let keyboard = &KEYBOARD_STATE;
+    for keycode in 0..KEY_COUNT {
+        if keyboard.pressed_keys.get(keycode) {
+            keyboard.pressed_keys.set(keycode, false);
+            key_event::push_key_event(KeyEvent {
+                key: KeyboardKey::from_keycode(keycode),
+                scan_code: 0,
+                is_pressed: false,
+                time,
+            });
+        }
+    }
+    for index in 0..SCAN_CODE_COUNT {
+        if keyboard.pressed_scan_codes.get(index) {
+            keyboard.pressed_scan_codes.set(index, false);
+            key_event::push_key_event(KeyEvent {
+                key: KeyboardKey::Unknown(0),
+                scan_code: scan_code_from_index(index),
+                is_pressed: false,
+                time,
+            });
+        }
+    }
 }
@@ -0,0 +1,43 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+// Only the newest are kept, whoever shows them only has room for a few.
+const MAX_HELD_MESSAGES: usize = 64;
+
+// None while messages go straight to stdout.
+static HELD_MESSAGES: Mutex<Option<VecDeque<String>>> = Mutex::new(None);
+
+// Everything the program has to tell the user while running goes through here, so
+// something that has taken over the terminal can hold the messages and show them itself.
+pub fn print_message(message: String) {
+    let mut held_messages = HELD_MESSAGES.lock().unwrap();
+    match held_messages.as_mut() {
+        Some(held_messages) => {
+            if held_messages.len() >= MAX_HELD_MESSAGES {
+                held_messages.pop_front();
+            }
+            held_messages.push_back(message);
+        },
+        None => println!("{}", message),
+    }
+}
+
+pub fn hold_messages() {
+    HELD_MESSAGES.lock().unwrap().get_or_insert_with(VecDeque::new);
+}
+
+// Prints anything still held and goes back to printing right away.
+pub fn release_messages() {
+    if let Some(held_messages) = HELD_MESSAGES.lock().unwrap().take() {
+        for message in held_messages {
+            println!("{}", message);
+        }
+    }
+}
+
+pub fn take_held_messages() -> Vec<String> {
+    HELD_MESSAGES.lock().unwrap()
+        .as_mut()
+        .map(|held_messages| held_messages.drain(..).collect())
+        .unwrap_or_default()
+}
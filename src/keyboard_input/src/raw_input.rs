@@ -0,0 +1,283 @@
+use std::mem;
+use std::ptr::null_mut;
+use winapi::{
+    ctypes::*,
+    shared::hidsdi::HidD_GetProductString,
+    shared::minwindef::*,
+    shared::windef::HWND,
+    um::fileapi::{CreateFileW, OPEN_EXISTING},
+    um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+    um::winnt::{FILE_SHARE_READ, FILE_SHARE_WRITE, HANDLE},
+    um::winuser::*,
+};
+
+use crate::{KeyboardKey, KeyboardDevice, KeyboardDeviceSelector, handle_key, release_all_keys, print_message};
+use crate::input_backend::{InputBackendHealth, input_backend_health, set_input_backend_health};
+use crate::keyboard_device::is_selected;
+
+const USAGE_PAGE_GENERIC_DESKTOP: USHORT = 0x01;
+const USAGE_KEYBOARD: USHORT = 0x06;
+// Raw Input functions return this on failure.
+const RAW_INPUT_ERROR: UINT = UINT::MAX;
+
+// Raw Input says which keyboard each key came from, which the hook can't. It is
+// only used while a keyboard is selected, otherwise the hook reads every key.
+// The hook runs before Raw Input sees a key, so a key can't be blocked based on
+// the keyboard it came from. A selected keyboard's keys always reach other programs.
+pub(crate) struct RawInput {
+    window: HWND,
+    // Keyboards looked up so far, by Raw Input device handle.
+    devices: Vec<(HANDLE, KeyboardDevice)>,
+    // Keys pressed on a selected keyboard and not yet released, as given to handle_key.
+    held_keys: Vec<(HANDLE, KeyboardKey, u16)>,
+    selector: Option<KeyboardDeviceSelector>,
+    // None until the keyboards are looked through again.
+    is_selected_keyboard_connected: Option<bool>,
+}
+
+impl RawInput {
+    // Keys are sent to a message only window on the calling thread, even while
+    // another program has focus.
+    pub(crate) unsafe fn register() -> Option<Self> {
+        let class_name = wide("STATIC");
+        let window = CreateWindowExW(0, class_name.as_ptr(), null_mut(), 0, 0, 0, 0, 0, HWND_MESSAGE, null_mut(), null_mut(), null_mut());
+        if window.is_null() {
+            print_message("Could not create the Raw Input window, keyboard_device in config.json will be ignored.".to_string());
+            return None;
+        }
+        let device = RAWINPUTDEVICE {
+            usUsagePage: USAGE_PAGE_GENERIC_DESKTOP,
+            usUsage: USAGE_KEYBOARD,
+            dwFlags: RIDEV_INPUTSINK | RIDEV_DEVNOTIFY,
+            hwndTarget: window,
+        };
+        if RegisterRawInputDevices(&device, 1, mem::size_of::<RAWINPUTDEVICE>() as UINT) == FALSE {
+            print_message("Could not register for Raw Input, keyboard_device in config.json will be ignored.".to_string());
+            DestroyWindow(window);
+            return None;
+        }
+        Some(RawInput {
+            window,
+            devices: Vec::new(),
+            held_keys: Vec::new(),
+            selector: None,
+            is_selected_keyboard_connected: None,
+        })
+    }
+
+    pub(crate) unsafe fn handle_input(&mut self, l_param: LPARAM) {
+        let mut input: RAWINPUT = mem::zeroed();
+        let mut size = mem::size_of::<RAWINPUT>() as UINT;
+        let result = GetRawInputData(
+            l_param as HRAWINPUT,
+            RID_INPUT,
+            &mut input as *mut RAWINPUT as LPVOID,
+            &mut size,
+            mem::size_of::<RAWINPUTHEADER>() as UINT,
+        );
+        if result == RAW_INPUT_ERROR || input.header.dwType != RIM_TYPEKEYBOARD {
+            return;
+        }
+        self.update();
+        if self.selector.is_none() {
+            return;
+        }
+        let device = input.header.hDevice;
+        if !self.device(device).is_some_and(is_selected) {
+            return;
+        }
+
+        let keyboard = input.data.keyboard();
+        // 0xFF is sent for the fake keys around Pause and Print Screen.
+        if keyboard.VKey == 0xFF || keyboard.MakeCode as DWORD == KEYBOARD_OVERRUN_MAKE_CODE {
+            return;
+        }
+        let flags = keyboard.Flags as DWORD;
+        let is_extended = flags & RI_KEY_E0 != 0;
+        // Raw Input doesn't say which side these are on, the hook does.
+        let keycode = match keyboard.VKey as c_int {
+            VK_SHIFT => MapVirtualKeyW(keyboard.MakeCode as UINT, MAPVK_VSC_TO_VK_EX) as c_int,
+            VK_CONTROL => if is_extended { VK_RCONTROL } else { VK_LCONTROL },
+            VK_MENU => if is_extended { VK_RMENU } else { VK_LMENU },
+            keycode => keycode,
+        };
+        let key = KeyboardKey::from_keycode(keycode as usize);
+        let mut scan_code = keyboard.MakeCode & 0xFF;
+        if is_extended {
+            scan_code |= 0xE000;
+        }
+        let is_pressed = flags & RI_KEY_BREAK == 0;
+
+        self.held_keys.retain(|&held_key| held_key != (device, key, scan_code));
+        if is_pressed {
+            self.held_keys.push((device, key, scan_code));
+        }
+        handle_key(key, scan_code, is_pressed);
+    }
+
+    // Releases are never seen for keys held on a keyboard that is unplugged. Keys
+    // that another selected keyboard is still holding are left pressed.
+    pub(crate) unsafe fn handle_device_change(&mut self, w_param: WPARAM, l_param: LPARAM) {
+        let device = l_param as HANDLE;
+        if w_param as DWORD == GIDC_REMOVAL {
+            if let Some(info) = self.devices.iter().find(|(handle, _)| *handle == device).map(|(_, info)| info) {
+                if self.selector.is_some() && is_selected(info) {
+                    print_message(format!("Lost keyboard {} ({}).", info.name, info.path));
+                }
+            }
+            let (lost_keys, held_keys): (Vec<_>, Vec<_>) = self.held_keys.drain(..).partition(|&(handle, _, _)| handle == device);
+            self.held_keys = held_keys;
+            for (_, key, scan_code) in lost_keys {
+                if !self.held_keys.iter().any(|&(_, held_key, held_scan_code)| (held_key, held_scan_code) == (key, scan_code)) {
+                    handle_key(key, scan_code, false);
+                }
+            }
+            self.devices.retain(|(handle, _)| *handle != device);
+        }
+        self.recheck_connection();
+    }
+
+    // For when something else may have changed the backend health.
+    pub(crate) unsafe fn recheck_connection(&mut self) {
+        self.is_selected_keyboard_connected = None;
+        self.update();
+    }
+
+    // Picks up changes to the selected keyboard, and holds the backend as
+    // disconnected while no selected keyboard is plugged in.
+    pub(crate) unsafe fn update(&mut self) {
+        let selector = crate::keyboard_device();
+        if selector != self.selector {
+            let was_reading_selected_keyboard = self.selector.is_some();
+            self.selector = selector;
+            self.is_selected_keyboard_connected = None;
+            self.held_keys.clear();
+            release_all_keys();
+            if self.selector.is_none() {
+                if input_backend_health() == InputBackendHealth::Disconnected {
+                    set_input_backend_health(InputBackendHealth::Installed);
+                }
+            }
+            else if !was_reading_selected_keyboard {
+                print_message("Reading keys from the selected keyboard only. Windows can't block them by keyboard, so they still reach other programs.".to_string());
+            }
+        }
+        if self.selector.is_none() || self.is_selected_keyboard_connected.is_some() {
+            return;
+        }
+
+        let is_connected = keyboard_handles().into_iter().any(|device| self.device(device).is_some_and(is_selected));
+        self.is_selected_keyboard_connected = Some(is_connected);
+        if is_connected {
+            if input_backend_health() == InputBackendHealth::Disconnected {
+                set_input_backend_health(InputBackendHealth::Installed);
+            }
+        }
+        else {
+            print_message("The selected keyboard isn't plugged in, waiting for it...".to_string());
+            self.held_keys.clear();
+            release_all_keys();
+            set_input_backend_health(InputBackendHealth::Disconnected);
+        }
+    }
+
+    unsafe fn device(&mut self, device: HANDLE) -> Option<&KeyboardDevice> {
+        let index = match self.devices.iter().position(|(handle, _)| *handle == device) {
+            Some(index) => index,
+            None => {
+                self.devices.push((device, keyboard_device_info(device)?));
+                self.devices.len() - 1
+            },
+        };
+        Some(&self.devices[index].1)
+    }
+}
+
+impl Drop for RawInput {
+    fn drop(&mut self) {
+        let device = RAWINPUTDEVICE {
+            usUsagePage: USAGE_PAGE_GENERIC_DESKTOP,
+            usUsage: USAGE_KEYBOARD,
+            dwFlags: RIDEV_REMOVE,
+            hwndTarget: null_mut(),
+        };
+        unsafe {
+            RegisterRawInputDevices(&device, 1, mem::size_of::<RAWINPUTDEVICE>() as UINT);
+            DestroyWindow(self.window);
+        }
+    }
+}
+
+pub(crate) fn keyboard_devices() -> Vec<KeyboardDevice> {
+    let mut devices: Vec<KeyboardDevice> = unsafe {
+        keyboard_handles().into_iter().filter_map(|device| keyboard_device_info(device)).collect()
+    };
+    devices.sort_by(|a, b| a.path.cmp(&b.path));
+    devices
+}
+
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+fn from_wide(text: &[u16]) -> String {
+    let length = text.iter().position(|&c| c == 0).unwrap_or(text.len());
+    String::from_utf16_lossy(&text[..length])
+}
+
+unsafe fn keyboard_handles() -> Vec<HANDLE> {
+    let entry_size = mem::size_of::<RAWINPUTDEVICELIST>() as UINT;
+    let mut count: UINT = 0;
+    if GetRawInputDeviceList(null_mut(), &mut count, entry_size) == RAW_INPUT_ERROR {
+        return Vec::new();
+    }
+    let mut entries = vec![RAWINPUTDEVICELIST { hDevice: null_mut(), dwType: 0 }; count as usize];
+    let count = GetRawInputDeviceList(entries.as_mut_ptr(), &mut count, entry_size);
+    if count == RAW_INPUT_ERROR {
+        return Vec::new();
+    }
+    entries.truncate(count as usize);
+    entries.into_iter()
+        .filter(|entry| entry.dwType == RIM_TYPEKEYBOARD)
+        .map(|entry| entry.hDevice)
+        .collect()
+}
+
+// The path is the device interface name, e.g. \\?\HID#VID_046D&PID_C31C&MI_00#...
+// The name is the product string for HID keyboards and the path for the rest.
+unsafe fn keyboard_device_info(device: HANDLE) -> Option<KeyboardDevice> {
+    let mut length: UINT = 0;
+    GetRawInputDeviceInfoW(device, RIDI_DEVICENAME, null_mut(), &mut length);
+    let mut interface_name = vec![0u16; length as usize + 1];
+    if GetRawInputDeviceInfoW(device, RIDI_DEVICENAME, interface_name.as_mut_ptr() as LPVOID, &mut length) == RAW_INPUT_ERROR {
+        return None;
+    }
+    let path = from_wide(&interface_name);
+    let upper_case_path = path.to_uppercase();
+    let read_id = |prefix: &str| upper_case_path.find(prefix)
+        .and_then(|index| upper_case_path.get(index + prefix.len()..index + prefix.len() + 4))
+        .and_then(|id| u16::from_str_radix(id, 16).ok())
+        .unwrap_or(0);
+    Some(KeyboardDevice {
+        name: product_string(&interface_name).unwrap_or_else(|| path.clone()),
+        vendor_id: read_id("VID_"),
+        product_id: read_id("PID_"),
+        physical_path: String::new(),
+        path,
+    })
+}
+
+// Opening with no access is enough to read the strings and works for keyboards,
+// which Windows keeps open for itself.
+unsafe fn product_string(interface_name: &[u16]) -> Option<String> {
+    let handle = CreateFileW(interface_name.as_ptr(), 0, FILE_SHARE_READ | FILE_SHARE_WRITE, null_mut(), OPEN_EXISTING, 0, null_mut());
+    if handle == INVALID_HANDLE_VALUE {
+        return None;
+    }
+    // HID strings are at most 126 characters and a terminator.
+    let mut buffer = [0u16; 127];
+    let result = HidD_GetProductString(handle, buffer.as_mut_ptr() as LPVOID, mem::size_of_val(&buffer) as ULONG);
+    CloseHandle(handle);
+    let name = from_wide(&buffer);
+    if result == 0 || name.trim().is_empty() { None } else { Some(name.trim().to_string()) }
+}
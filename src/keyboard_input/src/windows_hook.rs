@@ -0,0 +1,148 @@
+use std::thread;
+use std::time::Duration;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicU32, Ordering};
+use winapi::{
+    ctypes::*,
+    shared::{minwindef::*},
+    shared::{windef::*},
+    um::processthreadsapi::GetCurrentThreadId,
+    um::winuser::*,
+};
+
+use crate::{KeyboardKey, KEY_COUNT, handle_key, release_all_keys, print_message};
+use crate::input_backend::{InputBackendHealth, set_input_backend_health, stop_requested};
+use crate::raw_input::RawInput;
+
+const HEALTH_CHECK_INTERVAL_MS: u32 = 1000;
+const REINSTALL_DELAY: Duration = Duration::from_millis(1000);
+
+static HOOK_THREAD_ID: AtomicU32 = AtomicU32::new(0);
+
+// Windows silently removes hooks that take too long to respond, so the hook is checked
+// every so often and installed again if it has stopped seeing keys.
+pub(crate) fn run() {
+    unsafe {
+        HOOK_THREAD_ID.store(GetCurrentThreadId(), Ordering::Release);
+
+        // Make sure the thread has a message queue before anyone posts to it.
+        let mut msg = empty_message();
+        PeekMessageW(&mut msg, null_mut(), 0, 0, PM_NOREMOVE);
+
+        let mut raw_input = RawInput::register();
+
+        while !stop_requested() {
+            let hook = SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_hook), 0 as HINSTANCE, 0);
+            if hook.is_null() {
+                print_message("Could not install the keyboard hook, retrying...".to_string());
+                set_input_backend_health(InputBackendHealth::Disconnected);
+                thread::sleep(REINSTALL_DELAY);
+                continue;
+            }
+            set_input_backend_health(InputBackendHealth::Installed);
+            if let Some(raw_input) = &mut raw_input {
+                raw_input.recheck_connection();
+            }
+            hook_missed_key_presses();
+            let timer = SetTimer(null_mut(), 0, HEALTH_CHECK_INTERVAL_MS, None);
+
+            let mut should_reinstall = false;
+            while GetMessageW(&mut msg, null_mut(), 0, 0) > 0 {
+                match (msg.message, &mut raw_input) {
+                    (WM_INPUT, Some(raw_input)) => raw_input.handle_input(msg.lParam),
+                    (WM_INPUT_DEVICE_CHANGE, Some(raw_input)) => raw_input.handle_device_change(msg.wParam, msg.lParam),
+                    (WM_TIMER, _) => {
+                        if stop_requested() {
+                            break;
+                        }
+                        if let Some(raw_input) = &mut raw_input {
+                            raw_input.update();
+                        }
+                        // The hook isn't reading keys while a keyboard is selected.
+                        if hook_missed_key_presses() && crate::keyboard_device().is_none() {
+                            should_reinstall = true;
+                            break;
+                        }
+                    },
+                    _ => (),
+                }
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+
+            KillTimer(null_mut(), timer);
+            UnhookWindowsHookEx(hook);
+            if !should_reinstall {
+                break;
+            }
+            print_message("The keyboard hook stopped receiving keys, reinstalling it.".to_string());
+            set_input_backend_health(InputBackendHealth::Disconnected);
+            release_all_keys();
+        }
+
+        drop(raw_input);
+        HOOK_THREAD_ID.store(0, Ordering::Release);
+    }
+}
+
+pub(crate) fn wake() {
+    let thread_id = HOOK_THREAD_ID.load(Ordering::Acquire);
+    if thread_id != 0 {
+        unsafe { PostThreadMessageW(thread_id, WM_QUIT, 0, 0); }
+    }
+}
+
+fn empty_message() -> MSG {
+    MSG {
+        hwnd: null_mut(),
+        message: 0,
+        wParam: 0,
+        lParam: 0,
+        time: 0,
+        pt: POINT { x: 0, y: 0 },
+    }
+}
+
+// Keys that were pressed since the last check without the hook seeing them mean
+// the hook is gone. Keys the hook blocks never show up here, so they can't trip it.
+// Every key is queried so the "pressed since the last check" bits are all cleared.
+unsafe fn hook_missed_key_presses() -> bool {
+    let mut missed_key_presses = false;
+    for keycode in 8..KEY_COUNT {
+        let key = KeyboardKey::from_keycode(keycode);
+        // The hook only reports the left and right versions of these.
+        if matches!(key, KeyboardKey::Shift | KeyboardKey::Control | KeyboardKey::Alt) {
+            continue;
+        }
+        let state = GetAsyncKeyState(keycode as c_int) as u16;
+        if state & 0x8001 == 0x8001 && !crate::key_is_pressed(key) {
+            missed_key_presses = true;
+        }
+    }
+    missed_key_presses
+}
+
+unsafe extern "system" fn keyboard_hook(code: c_int, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+    let mut block_key_press = false;
+
+    // Keys from a selected keyboard are read with Raw Input instead, see raw_input.rs.
+    let key_is_pressed = match w_param as u32 {
+        _ if crate::keyboard_device().is_some() => None,
+        WM_KEYDOWN | WM_SYSKEYDOWN => Some(true),
+        WM_KEYUP | WM_SYSKEYUP => Some(false),
+        _ => None,
+    };
+
+    if let Some(key_is_pressed) = key_is_pressed {
+        let hook_struct = &*(l_param as *const KBDLLHOOKSTRUCT);
+        let keyboard_key = KeyboardKey::from_keycode(hook_struct.vkCode as usize);
+        let mut scan_code = (hook_struct.scanCode & 0xFF) as u16;
+        if hook_struct.flags & LLKHF_EXTENDED != 0 {
+            scan_code |= 0xE000;
+        }
+        block_key_press = handle_key(keyboard_key, scan_code, key_is_pressed);
+    }
+
+    if block_key_press { 1 }
+    else { CallNextHookEx(null_mut(), code, w_param, l_param) }
+}
@@ -0,0 +1,498 @@
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+use keyboard_input::print_message;
+
+const REPLY_TIMEOUT: Duration = Duration::from_millis(2000);
+const MAX_LINE_LENGTH: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlServerSettings {
+    pub enabled: bool,
+    // A Unix domain socket path, or a named pipe like \\.\pipe\keyboard_melee on Windows.
+    pub path: String,
+}
+
+impl Default for ControlServerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: platform::default_path(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ControllerToggle {
+    ShortHopMacro,
+    CStickTilting,
+    ExtraBButtons,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlCommand {
+    // None goes back to config.json.
+    SwitchProfile(Option<String>),
+    // None flips the current value.
+    SetToggle(ControllerToggle, Option<bool>),
+    SetPaused(Option<bool>),
+    QueryState,
+    ReloadConfig,
+    // None starts the playback that is already armed.
+    StartPlayback(Option<String>),
+    StopPlayback,
+}
+
+// Commands are one per line:
+//   profile <name>|default
+//   set|toggle use_short_hop_macro|use_c_stick_tilting|use_extra_b_buttons [true|false]
+//   pause | resume | toggle_pause
+//   state
+//   reload
+//   playback [<file>] | playback stop
+// Every command is answered with one line of JSON.
+pub fn parse_control_command(line: &str) -> Result<ControlCommand, String> {
+    // The file can have spaces in it, so it is everything after the command.
+    if let Some(file) = line.trim().strip_prefix("playback ") {
+        return match file.trim() {
+            "stop" => Ok(ControlCommand::StopPlayback),
+            file => Ok(ControlCommand::StartPlayback(Some(file.to_string()))),
+        };
+    }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let parse_toggle = |name: &str| match name {
+        "use_short_hop_macro" => Ok(ControllerToggle::ShortHopMacro),
+        "use_c_stick_tilting" => Ok(ControllerToggle::CStickTilting),
+        "use_extra_b_buttons" => Ok(ControllerToggle::ExtraBButtons),
+        _ => Err(format!("Unknown setting \"{}\".", name)),
+    };
+    let parse_bool = |value: &str| match value {
+        "true" | "on" => Ok(true),
+        "false" | "off" => Ok(false),
+        _ => Err(format!("Expected true or false, got \"{}\".", value)),
+    };
+    match words.as_slice() {
+        ["profile", "default"] => Ok(ControlCommand::SwitchProfile(None)),
+        ["profile", name] => {
+            let is_valid_name = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if is_valid_name {
+                Ok(ControlCommand::SwitchProfile(Some(name.to_string())))
+            }
+            else {
+                Err("Profile names can only use letters, numbers, - and _.".to_string())
+            }
+        },
+        ["set", name, value] => Ok(ControlCommand::SetToggle(parse_toggle(name)?, Some(parse_bool(value)?))),
+        ["toggle", name] => Ok(ControlCommand::SetToggle(parse_toggle(name)?, None)),
+        ["pause"] => Ok(ControlCommand::SetPaused(Some(true))),
+        ["resume"] => Ok(ControlCommand::SetPaused(Some(false))),
+        ["toggle_pause"] => Ok(ControlCommand::SetPaused(None)),
+        ["state"] => Ok(ControlCommand::QueryState),
+        ["reload"] => Ok(ControlCommand::ReloadConfig),
+        ["playback"] => Ok(ControlCommand::StartPlayback(None)),
+        _ => Err(format!("Unknown command \"{}\".", line.trim())),
+    }
+}
+
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: Sender<String>,
+}
+
+impl ControlRequest {
+    pub fn respond(self, reply: String) {
+        let _ = self.reply.send(reply);
+    }
+}
+
+// Commands are handed to the update loop, which owns the controller, and the
+// connection waits for the answer.
+pub struct ControlServer {
+    requests: Receiver<ControlRequest>,
+    is_stopping: Arc<AtomicBool>,
+    path: String,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ControlServer {
+    pub fn start(settings: &ControlServerSettings) -> io::Result<Self> {
+        let (request_sender, requests) = mpsc::channel();
+        let is_stopping = Arc::new(AtomicBool::new(false));
+        let listener = platform::Listener::bind(&settings.path)?;
+        let thread_is_stopping = is_stopping.clone();
+        let thread = thread::spawn(move || listener.run(request_sender, thread_is_stopping));
+        print_message(format!("Listening for control commands on {}", settings.path));
+        Ok(Self {
+            requests,
+            is_stopping,
+            path: settings.path.clone(),
+            thread: Some(thread),
+        })
+    }
+
+    pub fn try_recv(&self) -> Option<ControlRequest> {
+        self.requests.try_recv().ok()
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.is_stopping.store(true, Ordering::Release);
+        platform::wake(&self.path);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        platform::clean_up(&self.path);
+    }
+}
+
+fn error_reply(error: &str) -> String {
+    serde_json::json!({ "ok": false, "error": error }).to_string()
+}
+
+fn handle_client<S: Read + Write>(mut stream: S, requests: Sender<ControlRequest>) {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        match stream.read(&mut byte) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => (),
+        }
+        if byte[0] != b'\n' {
+            if line.len() < MAX_LINE_LENGTH {
+                line.push(byte[0]);
+            }
+            continue;
+        }
+
+        let text = String::from_utf8_lossy(&line).trim().to_string();
+        line.clear();
+        if text.is_empty() {
+            continue;
+        }
+        let reply = match parse_control_command(&text) {
+            Ok(command) => {
+                let (reply_sender, reply_receiver) = mpsc::channel();
+                if requests.send(ControlRequest { command, reply: reply_sender }).is_err() {
+                    return;
+                }
+                reply_receiver.recv_timeout(REPLY_TIMEOUT)
+                    .unwrap_or_else(|_| error_reply("The controller did not answer."))
+            },
+            Err(e) => error_reply(&e),
+        };
+        if stream.write_all(reply.as_bytes()).is_err()
+        || stream.write_all(b"\n").is_err()
+        || stream.flush().is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::Sender;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{ControlRequest, handle_client};
+
+    const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+
+    pub fn default_path() -> String {
+        let directory = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        directory.join("keyboard_melee.sock").to_string_lossy().into_owned()
+    }
+
+    pub struct Listener(UnixListener);
+
+    impl Listener {
+        // Only the current user can connect. The socket is made inside a private directory
+        // and then linked into place, so nobody can connect before its permissions are set.
+        pub fn bind(path: &str) -> io::Result<Self> {
+            remove_stale_socket(path)?;
+            let path = Path::new(path);
+            let private_directory = path.with_file_name(format!(".keyboard_melee-{}", std::process::id()));
+            fs::DirBuilder::new().mode(0o700).create(&private_directory)?;
+            let private_path = private_directory.join("socket");
+            let result = UnixListener::bind(&private_path).and_then(|listener| {
+                fs::set_permissions(&private_path, fs::Permissions::from_mode(0o600))?;
+                // Unlike a rename, this fails rather than replacing anything at the path.
+                fs::hard_link(&private_path, path)?;
+                listener.set_nonblocking(true)?;
+                Ok(Listener(listener))
+            });
+            let _ = fs::remove_file(&private_path);
+            let _ = fs::remove_dir(&private_directory);
+            result
+        }
+
+        pub fn run(self, requests: Sender<ControlRequest>, is_stopping: Arc<AtomicBool>) {
+            while !is_stopping.load(Ordering::Acquire) {
+                match self.0.accept() {
+                    Ok((stream, _)) => {
+                        if stream.set_nonblocking(false).is_ok() {
+                            let requests = requests.clone();
+                            thread::spawn(move || handle_client(stream, requests));
+                        }
+                    },
+                    Err(_) => thread::sleep(ACCEPT_INTERVAL),
+                }
+            }
+        }
+    }
+
+    // A socket left behind by a crash would make bind fail. Anything that isn't a
+    // socket, or that another instance is still listening on, is left alone.
+    fn remove_stale_socket(path: &str) -> io::Result<()> {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if !metadata.file_type().is_socket() => {
+                Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path)))
+            },
+            Ok(_) if UnixStream::connect(path).is_ok() => {
+                Err(io::Error::new(io::ErrorKind::AddrInUse, format!("something is already listening on {}", path)))
+            },
+            Ok(_) => fs::remove_file(path),
+            Err(_) => Ok(()),
+        }
+    }
+
+    pub fn wake(_path: &str) {}
+
+    pub fn clean_up(path: &str) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::OsStr;
+    use std::io::{self, Read, Write};
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr::null_mut;
+    use std::slice;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::Sender;
+    use std::thread;
+    use winapi::{
+        shared::minwindef::{DWORD, FALSE},
+        shared::sddl::{ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+        shared::winerror::ERROR_PIPE_CONNECTED,
+        um::errhandlingapi::GetLastError,
+        um::fileapi::{CreateFileW, ReadFile, WriteFile, FlushFileBuffers, OPEN_EXISTING},
+        um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+        um::minwinbase::SECURITY_ATTRIBUTES,
+        um::namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe},
+        um::processthreadsapi::{GetCurrentProcess, OpenProcessToken},
+        um::securitybaseapi::GetTokenInformation,
+        um::winbase::{
+            LocalFree,
+            FILE_FLAG_FIRST_PIPE_INSTANCE,
+            PIPE_ACCESS_DUPLEX,
+            PIPE_READMODE_BYTE,
+            PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_TYPE_BYTE,
+            PIPE_UNLIMITED_INSTANCES,
+            PIPE_WAIT,
+        },
+        um::winnt::{GENERIC_READ, GENERIC_WRITE, HANDLE, PSECURITY_DESCRIPTOR, TOKEN_QUERY, TOKEN_USER, TokenUser},
+    };
+
+    use super::{ControlRequest, handle_client, print_message};
+
+    const BUFFER_SIZE: DWORD = 4096;
+
+    pub fn default_path() -> String {
+        r"\\.\pipe\keyboard_melee".to_string()
+    }
+
+    fn wide(path: &str) -> Vec<u16> {
+        OsStr::new(path).encode_wide().chain(std::iter::once(0)).collect()
+    }
+
+    struct Pipe(HANDLE);
+
+    // The handle is only ever used by the thread it is moved to.
+    unsafe impl Send for Pipe {}
+
+    impl Read for Pipe {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let mut byte_count: DWORD = 0;
+            let result = unsafe {
+                ReadFile(self.0, buffer.as_mut_ptr() as *mut _, buffer.len() as DWORD, &mut byte_count, null_mut())
+            };
+            if result == 0 { Err(io::Error::last_os_error()) } else { Ok(byte_count as usize) }
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            let mut byte_count: DWORD = 0;
+            let result = unsafe {
+                WriteFile(self.0, buffer.as_ptr() as *const _, buffer.len() as DWORD, &mut byte_count, null_mut())
+            };
+            if result == 0 { Err(io::Error::last_os_error()) } else { Ok(byte_count as usize) }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            if unsafe { FlushFileBuffers(self.0) } == 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+        }
+    }
+
+    impl Drop for Pipe {
+        fn drop(&mut self) {
+            unsafe {
+                DisconnectNamedPipe(self.0);
+                CloseHandle(self.0);
+            }
+        }
+    }
+
+    // The SID of the user running this, like "S-1-5-21-...".
+    fn current_user_sid() -> io::Result<String> {
+        unsafe {
+            let mut token = null_mut();
+            if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut length: DWORD = 0;
+            GetTokenInformation(token, TokenUser, null_mut(), 0, &mut length);
+            // u64s keep the TOKEN_USER at the start aligned.
+            let mut buffer = vec![0u64; (length as usize).div_ceil(8)];
+            let result = GetTokenInformation(token, TokenUser, buffer.as_mut_ptr() as *mut _, length, &mut length);
+            CloseHandle(token);
+            if result == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let token_user = &*(buffer.as_ptr() as *const TOKEN_USER);
+
+            let mut sid_string = null_mut();
+            if ConvertSidToStringSidW(token_user.User.Sid, &mut sid_string) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let sid_length = (0..).take_while(|&i| *sid_string.add(i) != 0).count();
+            let sid = String::from_utf16_lossy(slice::from_raw_parts(sid_string, sid_length));
+            LocalFree(sid_string as *mut _);
+            Ok(sid)
+        }
+    }
+
+    // Lets only the user running this open the pipe.
+    struct SecurityDescriptor(PSECURITY_DESCRIPTOR);
+
+    // Never changed after it is made, only passed to CreateNamedPipeW.
+    unsafe impl Send for SecurityDescriptor {}
+
+    impl SecurityDescriptor {
+        fn current_user_only() -> io::Result<Self> {
+            let sddl = wide(&format!("D:P(A;;GA;;;{})", current_user_sid()?));
+            let mut descriptor = null_mut();
+            let result = unsafe {
+                ConvertStringSecurityDescriptorToSecurityDescriptorW(sddl.as_ptr(), SDDL_REVISION_1 as DWORD, &mut descriptor, null_mut())
+            };
+            if result == 0 { Err(io::Error::last_os_error()) } else { Ok(SecurityDescriptor(descriptor)) }
+        }
+    }
+
+    impl Drop for SecurityDescriptor {
+        fn drop(&mut self) {
+            unsafe { LocalFree(self.0); }
+        }
+    }
+
+    pub struct Listener {
+        name: Vec<u16>,
+        security_descriptor: SecurityDescriptor,
+        // Made in bind and kept for the first client, so the name is never left free.
+        first_instance: Option<Pipe>,
+    }
+
+    impl Listener {
+        // Fails if another program already has a pipe by this name, rather than sharing it.
+        pub fn bind(path: &str) -> io::Result<Self> {
+            let mut listener = Listener {
+                name: wide(path),
+                security_descriptor: SecurityDescriptor::current_user_only()?,
+                first_instance: None,
+            };
+            listener.first_instance = Some(listener.create_instance(FILE_FLAG_FIRST_PIPE_INSTANCE)?);
+            Ok(listener)
+        }
+
+        fn create_instance(&self, flags: DWORD) -> io::Result<Pipe> {
+            let mut security_attributes = SECURITY_ATTRIBUTES {
+                nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as DWORD,
+                lpSecurityDescriptor: self.security_descriptor.0,
+                bInheritHandle: FALSE,
+            };
+            let handle = unsafe {
+                CreateNamedPipeW(
+                    self.name.as_ptr(),
+                    PIPE_ACCESS_DUPLEX | flags,
+                    PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                    PIPE_UNLIMITED_INSTANCES,
+                    BUFFER_SIZE,
+                    BUFFER_SIZE,
+                    0,
+                    &mut security_attributes,
+                )
+            };
+            if handle == INVALID_HANDLE_VALUE { Err(io::Error::last_os_error()) } else { Ok(Pipe(handle)) }
+        }
+
+        pub fn run(mut self, requests: Sender<ControlRequest>, is_stopping: Arc<AtomicBool>) {
+            while !is_stopping.load(Ordering::Acquire) {
+                let instance = match self.first_instance.take() {
+                    Some(pipe) => Ok(pipe),
+                    None => self.create_instance(0),
+                };
+                let pipe = match instance {
+                    Ok(pipe) => pipe,
+                    Err(e) => {
+                        print_message(format!("Could not create the control pipe: {}", e));
+                        return;
+                    },
+                };
+                let is_connected = unsafe {
+                    ConnectNamedPipe(pipe.0, null_mut()) != 0 || GetLastError() == ERROR_PIPE_CONNECTED
+                };
+                if is_stopping.load(Ordering::Acquire) {
+                    return;
+                }
+                if is_connected {
+                    let requests = requests.clone();
+                    thread::spawn(move || handle_client(pipe, requests));
+                }
+            }
+        }
+    }
+
+    // ConnectNamedPipe blocks until someone connects, so connect to it.
+    pub fn wake(path: &str) {
+        let name = wide(path);
+        unsafe {
+            let handle = CreateFileW(name.as_ptr(), GENERIC_READ | GENERIC_WRITE, 0, null_mut(), OPEN_EXISTING, 0, null_mut());
+            if handle != INVALID_HANDLE_VALUE {
+                CloseHandle(handle);
+            }
+        }
+    }
+
+    pub fn clean_up(_path: &str) {}
+}
@@ -0,0 +1,244 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Deserialize};
+use keyboard_input::print_message;
+use digital_melee_controller::{
+    GameState,
+    SlippiEventStream,
+    game_state_from_event,
+    is_game_start_event,
+    is_game_end_event,
+    slippi_replay_header_size,
+};
+
+// The game sends a state every frame, so one this old means it is paused, in a menu or gone.
+const STALE_AFTER: Duration = Duration::from_millis(250);
+const REPLAY_READ_INTERVAL: Duration = Duration::from_millis(4);
+const REPLAY_RESCAN_INTERVAL: Duration = Duration::from_millis(1000);
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+// A file that goes this far without the events starting is not a replay.
+const MAX_REPLAY_HEADER_SIZE: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameStateFeedSettings {
+    pub enabled: bool,
+    // 1 to 4.
+    pub port: u8,
+    // A replay that is being written, or a folder to follow the newest replay in.
+    // Slippi writes replays as the game is played, so this follows a live game.
+    // Replays are the only source, Slippi's spectator connection is not supported.
+    pub replay_path: String,
+}
+
+impl Default for GameStateFeedSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 1,
+            replay_path: default_replay_folder(),
+        }
+    }
+}
+
+// Where Slippi saves replays unless told otherwise.
+fn default_replay_folder() -> String {
+    let home = std::env::var("USERPROFILE").or_else(|_| std::env::var("HOME")).unwrap_or_default();
+    Path::new(&home).join("Documents").join("Slippi").display().to_string()
+}
+
+struct Shared {
+    latest: Mutex<Option<(GameState, Instant)>>,
+    is_stopping: AtomicBool,
+}
+
+impl Shared {
+    fn set_latest(&self, game_state: Option<GameState>) {
+        *self.latest.lock().unwrap_or_else(PoisonError::into_inner) = game_state.map(|game_state| (game_state, Instant::now()));
+    }
+
+    fn is_stopping(&self) -> bool {
+        self.is_stopping.load(Ordering::Acquire)
+    }
+}
+
+// Follows the replay of the game being played on its own thread and keeps the latest state of one player.
+pub struct GameStateFeed {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl GameStateFeed {
+    pub fn start(settings: &GameStateFeedSettings) -> io::Result<Self> {
+        if !(1..=4).contains(&settings.port) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("port {} is not 1 to 4", settings.port)));
+        }
+        let port = settings.port - 1;
+
+        let shared = Arc::new(Shared {
+            latest: Mutex::new(None),
+            is_stopping: AtomicBool::new(false),
+        });
+        let thread_shared = shared.clone();
+        let path = PathBuf::from(&settings.replay_path);
+        print_message(format!("Following the game state for port {} from {}.", settings.port, path.display()));
+        let thread = thread::spawn(move || follow_contained(&thread_shared, |shared| follow_replays(&path, port, shared)));
+
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn game_state(&self) -> Option<GameState> {
+        match *self.shared.latest.lock().unwrap_or_else(PoisonError::into_inner) {
+            Some((game_state, time)) if time.elapsed() < STALE_AFTER => Some(game_state),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for GameStateFeed {
+    fn drop(&mut self) {
+        self.shared.is_stopping.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Anything going wrong while following a game only loses the game state, the controller plays on without it.
+fn follow_contained<F: FnOnce(&Shared)>(shared: &Shared, follow: F) {
+    if !crate::shutdown::run_contained(|| follow(shared)) {
+        shared.set_latest(None);
+        print_message("Stopped following the game state after an error, playing on without it.".to_string());
+    }
+}
+
+// Returns true once the game has ended.
+fn handle_events(events: &mut SlippiEventStream, bytes: &[u8], port: u8, shared: &Shared) -> io::Result<bool> {
+    events.push(bytes);
+    while let Some(event) = events.next_event()? {
+        if let Some(game_state) = game_state_from_event(event, port) {
+            shared.set_latest(Some(game_state));
+        }
+        else if is_game_start_event(event) {
+            shared.set_latest(None);
+        }
+        else if is_game_end_event(event) {
+            shared.set_latest(None);
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+struct FollowedReplay {
+    path: PathBuf,
+    file: File,
+    bytes_read: u64,
+    header: Vec<u8>,
+    events: Option<SlippiEventStream>,
+    is_finished: bool,
+}
+
+impl FollowedReplay {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        Ok(Self {
+            file: File::open(&path)?,
+            path,
+            bytes_read: 0,
+            header: Vec::new(),
+            events: None,
+            is_finished: false,
+        })
+    }
+
+    // Returns whether anything new was read.
+    fn read(&mut self, buffer: &mut [u8], port: u8, shared: &Shared) -> bool {
+        let length = self.file.read(buffer).unwrap_or_default();
+        if length == 0 {
+            return false;
+        }
+        self.bytes_read += length as u64;
+        let mut bytes = &buffer[..length];
+
+        // The events start after a short header, which may not all be written yet.
+        if self.events.is_none() {
+            self.header.extend_from_slice(bytes);
+            let header_size = match slippi_replay_header_size(&self.header) {
+                Some(header_size) => header_size,
+                None => {
+                    if self.header.len() >= MAX_REPLAY_HEADER_SIZE {
+                        print_message(format!("{} is not a Slippi replay.", self.path.display()));
+                        self.is_finished = true;
+                    }
+                    return true;
+                },
+            };
+            self.events = Some(SlippiEventStream::default());
+            bytes = &self.header[header_size..];
+        }
+
+        let events = self.events.as_mut().unwrap();
+        match handle_events(events, bytes, port, shared) {
+            Ok(has_ended) => self.is_finished = has_ended,
+            Err(e) => {
+                print_message(format!("Stopped following {}: {}", self.path.display(), e));
+                self.is_finished = true;
+            },
+        }
+        self.header.clear();
+        true
+    }
+
+    // A replay written again in the same place starts over.
+    fn was_rewritten(&self) -> bool {
+        fs::metadata(&self.path).is_ok_and(|metadata| metadata.len() < self.bytes_read)
+    }
+}
+
+fn follow_replays(path: &Path, port: u8, shared: &Shared) {
+    let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+    let mut replay: Option<FollowedReplay> = None;
+    let mut last_scan: Option<Instant> = None;
+    while !shared.is_stopping() {
+        if last_scan.is_none_or(|time| time.elapsed() >= REPLAY_RESCAN_INTERVAL) {
+            last_scan = Some(Instant::now());
+            if let Some(newest) = newest_replay(path) {
+                let is_new = replay.as_ref().is_none_or(|replay| replay.path != newest || replay.was_rewritten());
+                if is_new {
+                    shared.set_latest(None);
+                    replay = FollowedReplay::open(newest).ok();
+                }
+            }
+        }
+
+        let has_read = match &mut replay {
+            Some(replay) if !replay.is_finished => replay.read(&mut buffer, port, shared),
+            _ => false,
+        };
+        if !has_read {
+            thread::sleep(REPLAY_READ_INTERVAL);
+        }
+    }
+}
+
+fn newest_replay(path: &Path) -> Option<PathBuf> {
+    if !path.is_dir() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    fs::read_dir(path).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("slp")))
+        .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .max()
+        .map(|(_, path)| path)
+}
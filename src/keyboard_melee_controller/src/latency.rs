@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use digital_melee_controller::{Action, GameCubeControllerSnapshot};
+
+const BUCKET_WIDTH_US: u64 = 250;
+const BUCKET_COUNT: usize = 200;
+const HISTOGRAM_BAR_WIDTH: u64 = 40;
+
+// Output that hasn't changed this long after an action changed is assumed to not
+// be coming, e.g. pressing a button that was already held through another bind.
+const MAX_TECHNIQUE_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    buckets: Vec<u64>,
+    overflow_count: u64,
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKET_COUNT],
+            overflow_count: 0,
+            count: 0,
+            total: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn add(&mut self, latency: Duration) {
+        let bucket = (latency.as_micros() / BUCKET_WIDTH_US as u128) as usize;
+        match self.buckets.get_mut(bucket) {
+            Some(count) => *count += 1,
+            None => self.overflow_count += 1,
+        }
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn count(&self) -> u64 { self.count }
+    pub fn max(&self) -> Duration { self.max }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 { Duration::from_secs(0) } else { self.total / self.count as u32 }
+    }
+
+    // Rounded up to the end of the bucket the percentile falls in.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let target = ((percentile / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Duration::from_micros((bucket as u64 + 1) * BUCKET_WIDTH_US);
+            }
+        }
+        self.max
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{:>6} samples, mean {:>6.2} ms, p50 {:>6.2} ms, p99 {:>6.2} ms, max {:>6.2} ms",
+            self.count,
+            as_ms(self.mean()),
+            as_ms(self.percentile(50.0)),
+            as_ms(self.percentile(99.0)),
+            as_ms(self.max),
+        )
+    }
+
+    fn print_bars(&self) {
+        let largest_count = self.buckets.iter().copied().max().unwrap_or(0).max(self.overflow_count);
+        if largest_count == 0 {
+            return;
+        }
+        let first = self.buckets.iter().position(|count| *count > 0).unwrap_or(0);
+        let last = self.buckets.iter().rposition(|count| *count > 0).unwrap_or(0);
+        for bucket in first..=last {
+            let count = self.buckets[bucket];
+            let start_us = bucket as u64 * BUCKET_WIDTH_US;
+            println!(
+                "  {:>6.2} - {:>6.2} ms | {:<width$} {}",
+                start_us as f64 / 1000.0,
+                (start_us + BUCKET_WIDTH_US) as f64 / 1000.0,
+                "#".repeat((count * HISTOGRAM_BAR_WIDTH / largest_count) as usize),
+                count,
+                width = HISTOGRAM_BAR_WIDTH as usize,
+            );
+        }
+        if self.overflow_count > 0 {
+            println!("  {:>15} ms | {:<width$} {}",
+                format!(">= {:.2}", (BUCKET_COUNT as u64 * BUCKET_WIDTH_US) as f64 / 1000.0),
+                "#".repeat((self.overflow_count * HISTOGRAM_BAR_WIDTH / largest_count) as usize),
+                self.overflow_count,
+                width = HISTOGRAM_BAR_WIDTH as usize,
+            );
+        }
+    }
+}
+
+fn as_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+struct PendingAction {
+    action: Action,
+    event_time: Instant,
+    sent_time: Option<Instant>,
+}
+
+// Input latency is the time from the input backend seeing a key to the first
+// output sent after the action it is bound to changed. Technique delay is the time
+// from there until the output actually changed, which is how long techniques like
+// delayed buttons and stick tilting held the change back. When several actions change
+// together, the first output change is credited to all of them.
+#[derive(Default)]
+pub struct LatencyRecorder {
+    input_latency: LatencyHistogram,
+    action_input_latency: HashMap<Action, LatencyHistogram>,
+    action_technique_delay: HashMap<Action, LatencyHistogram>,
+    pending_actions: Vec<PendingAction>,
+    last_output: Option<GameCubeControllerSnapshot>,
+}
+
+impl LatencyRecorder {
+    pub fn record_action_change(&mut self, action: Action, event_time: Instant) {
+        self.pending_actions.push(PendingAction { action, event_time, sent_time: None });
+    }
+
+    pub fn record_output_sent(&mut self, time: Instant, output: &GameCubeControllerSnapshot) {
+        let output_changed = self.last_output.as_ref() != Some(output);
+        self.last_output = Some(*output);
+
+        for pending in &mut self.pending_actions {
+            if pending.sent_time.is_none() {
+                let latency = time.saturating_duration_since(pending.event_time);
+                pending.sent_time = Some(time);
+                self.input_latency.add(latency);
+                self.action_input_latency.entry(pending.action).or_default().add(latency);
+            }
+        }
+
+        if output_changed {
+            for pending in self.pending_actions.drain(..) {
+                let delay = time.saturating_duration_since(pending.sent_time.unwrap_or(time));
+                self.action_technique_delay.entry(pending.action).or_default().add(delay);
+            }
+        }
+        else {
+            self.pending_actions.retain(|pending| {
+                pending.sent_time.is_some_and(|sent_time| time - sent_time < MAX_TECHNIQUE_DELAY)
+            });
+        }
+    }
+
+    // Pending actions would otherwise be credited with whatever changes the output
+    // after input comes back.
+    pub fn forget_pending_actions(&mut self) {
+        self.pending_actions.clear();
+        self.last_output = None;
+    }
+
+    pub fn print(&self) {
+        println!("Input latency, key press to output:");
+        println!("  {}", self.input_latency.summary());
+        self.input_latency.print_bars();
+
+        let mut actions: Vec<Action> = self.action_input_latency.keys().copied().collect();
+        actions.sort_by_key(|action| format!("{:?}", action));
+
+        println!("Input latency by action:");
+        for action in &actions {
+            println!("  {:<20} {}", format!("{:?}", action), self.action_input_latency[action].summary());
+        }
+        println!("Technique delay by action, output held back on purpose:");
+        for action in &actions {
+            if let Some(histogram) = self.action_technique_delay.get(action) {
+                println!("  {:<20} {}", format!("{:?}", action), histogram.summary());
+            }
+        }
+    }
+}
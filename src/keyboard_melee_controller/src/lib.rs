@@ -1,48 +1,564 @@
-use std::collections::HashMap;
+mod control_server;
+mod game_state_feed;
+mod latency;
+mod output_recording;
+mod overlay_server;
+mod playback;
+mod scheduler;
+mod shutdown;
+mod vjoy_capabilities;
+
+use std::collections::{HashMap, VecDeque};
 use std::fs::{self, File};
 use std::io::prelude::*;
+use std::time::Instant;
+
+pub use keyboard_input::{InputBackendHealth, print_message, hold_messages, release_messages, take_held_messages};
+pub use crate::control_server::{
+    ControlServer,
+    ControlServerSettings,
+    ControlCommand,
+    ControlRequest,
+    ControllerToggle,
+    parse_control_command,
+};
+pub use crate::game_state_feed::{GameStateFeed, GameStateFeedSettings};
+pub use crate::latency::{LatencyHistogram, LatencyRecorder};
+pub use crate::output_recording::OutputRecording;
+pub use crate::overlay_server::{OverlayServer, OverlayServerSettings};
+pub use crate::playback::Playback;
+pub use crate::scheduler::{Scheduler, SchedulerSettings, TickJitter};
+pub use crate::shutdown::{install_shutdown_handlers, request_shutdown, shutdown_requested};
 
 use serde::{Serialize, Deserialize};
-use keyboard_input::{self, KeyboardKey};
-use vjoy_device::{VJoyDevice, VJoyAxis};
+use keyboard_input::{self, KeyboardKey, KeyBind, PhysicalKey, KeyEvent, KeyboardSnapshot, KeyboardDeviceSelector};
+use vjoy_device::{VJoyDevice, VJoyAxis, VJoyPovDirection, VJoyError};
 use digital_melee_controller::{
     Action,
     GameCubeControllerButton,
     GameCubeControllerAxis,
     GameCubeControllerSlider,
     DigitalMeleeController,
+    Character,
+    CharacterPresetOverrides,
+    TechniqueSettings,
 };
 
 pub struct KeyboardMeleeController {
     pub controller: DigitalMeleeController,
-    pub vjoy_device: VJoyDevice,
+    // None where there is no vJoy, the outputs then only reach the dashboard,
+    // the overlay and recordings.
+    pub vjoy_device: Option<VJoyDevice>,
     config: KeyboardMeleeControllerConfig,
+    is_vjoy_device_lost: bool,
+    input_backend_health: InputBackendHealth,
+    is_paused: bool,
+    is_shut_down: bool,
+    latency_recorder: Option<LatencyRecorder>,
+    output_recording: Option<OutputRecording>,
+    playback: Option<Playback>,
+    overlay_server: Option<OverlayServer>,
+    control_server: Option<ControlServer>,
+    game_state_feed: Option<GameStateFeed>,
+    // Whose preset the controller is using.
+    character: Character,
+    // None when running from config.json.
+    profile: Option<String>,
+    key_state: KeyboardSnapshot,
+    pending_key_events: VecDeque<KeyEvent>,
 }
 
 impl KeyboardMeleeController {
-    pub fn new() -> Self {
-        let mut controller = DigitalMeleeController::default();
+    pub fn new() -> Result<Self, VJoyError> {
         let config = KeyboardMeleeControllerConfig::load("config.json");
+
+        // Don't take over the keyboard unless there is somewhere to send the inputs.
+        // vJoy only exists on Windows, so elsewhere it runs without an output.
+        let vjoy_device = if cfg!(windows) {
+            let vjoy_device = VJoyDevice::new(config.vjoy_device_id, &config.vjoy_dll_path)?;
+            vjoy_capabilities::report_missing_vjoy_capabilities(&config, &vjoy_device);
+            Some(vjoy_device)
+        }
+        else {
+            print_message("vJoy is only available on Windows, running without a controller output.".to_string());
+            None
+        };
+
+        let mut overlay_server = None;
+        if config.overlay_server.enabled {
+            match OverlayServer::start(&config.overlay_server) {
+                Ok(server) => overlay_server = Some(server),
+                Err(e) => print_message(format!("Could not start the overlay server: {}", e)),
+            }
+        }
+
+        let mut control_server = None;
+        if config.control_server.enabled {
+            match ControlServer::start(&config.control_server) {
+                Ok(server) => control_server = Some(server),
+                Err(e) => print_message(format!("Could not start the control server: {}", e)),
+            }
+        }
+
+        let mut game_state_feed = None;
+        if config.game_state_feed.enabled {
+            match GameStateFeed::start(&config.game_state_feed) {
+                Ok(feed) => game_state_feed = Some(feed),
+                Err(e) => print_message(format!("Could not start the game state feed: {}", e)),
+            }
+        }
+
+        let mut output = Self {
+            controller: DigitalMeleeController::default(),
+            vjoy_device,
+            config,
+            is_vjoy_device_lost: false,
+            input_backend_health: InputBackendHealth::Disconnected,
+            is_paused: false,
+            is_shut_down: false,
+            latency_recorder: None,
+            output_recording: None,
+            playback: None,
+            overlay_server,
+            control_server,
+            game_state_feed,
+            character: Character::Generic,
+            profile: None,
+            key_state: Default::default(),
+            pending_key_events: VecDeque::new(),
+        };
+        output.apply_config();
+        keyboard_input::start_hook();
+        Ok(output)
+    }
+
+    // Everything in the config that can change while running. The vJoy device and
+    // the servers are only set up once, in new.
+    fn apply_config(&mut self) {
+        let config = &mut self.config;
+        if config.use_physical_key_binds {
+            config.key_binds = physical_key_binds(&config.key_binds);
+        }
+
+        let controller = &mut self.controller;
         controller.set_use_short_hop_macro(config.use_short_hop_macro);
         controller.set_use_c_stick_tilting(config.use_c_stick_tilting);
         controller.set_use_extra_b_buttons(config.use_extra_b_buttons);
+        controller.set_technique_settings(&config.technique);
 
-        keyboard_input::start_hook();
-        keyboard_input::block_all_keys();
-
-        Self {
-            controller,
-            vjoy_device: VJoyDevice::new(config.vjoy_device_id, &config.vjoy_dll_path),
-            config: config,
+        keyboard_input::set_pause_hotkey(config.pause_hotkey);
+        keyboard_input::unblock_all_keys();
+        keyboard_input::clear_passthrough_keys();
+        match config.key_blocking {
+            KeyBlocking::Nothing => (),
+            KeyBlocking::BoundKeys => {
+                for key_bind in config.key_binds.values().flatten() {
+                    keyboard_input::set_key_bind_blocked(*key_bind, true);
+                }
+            },
+            KeyBlocking::AllKeys => keyboard_input::block_all_keys(),
         }
+        for key_bind in &config.passthrough_keys {
+            keyboard_input::set_key_bind_passthrough(*key_bind, true);
+        }
+        select_keyboard_device(&config.keyboard_device);
+        self.set_character(self.config.character);
+    }
+
+    fn set_character(&mut self, character: Character) {
+        self.character = character;
+        self.controller.set_character_preset(&character.preset().with_overrides(&self.config.character_overrides));
     }
 
     pub fn update(&mut self) {
+        self.handle_control_requests();
+        self.update_game_state();
+        self.update_input_backend_health();
+        if self.input_backend_health == InputBackendHealth::Disconnected {
+            self.apply_all_key_events();
+            self.send_vjoy_device_inputs();
+            self.publish_output();
+            return;
+        }
+        if self.is_playing_back() {
+            self.apply_all_key_events();
+            self.update_controller_state_with_playback();
+            self.update_vjoy_device_outputs();
+            self.send_vjoy_device_inputs();
+            self.publish_output();
+            return;
+        }
+        self.update_pause_state();
+        if self.is_paused {
+            self.apply_all_key_events();
+            self.send_vjoy_device_inputs();
+            self.publish_output();
+            return;
+        }
+        self.apply_key_events_for_tick();
         self.update_controller_state_with_keys();
+        self.update_vjoy_device_outputs();
+        if self.send_vjoy_device_inputs() {
+            if let Some(recorder) = &mut self.latency_recorder {
+                recorder.record_output_sent(Instant::now(), &self.controller.controller_state.snapshot());
+            }
+        }
+        self.publish_output();
+    }
+
+    fn publish_output(&mut self) {
+        if let Some(recording) = &mut self.output_recording {
+            recording.record(Instant::now(), &self.controller.controller_state.snapshot());
+        }
+        if let Some(server) = &mut self.overlay_server {
+            server.publish(&self.controller.controller_state.snapshot(), &self.controller.held_actions(), self.is_paused);
+        }
+    }
+
+    pub fn enable_latency_recording(&mut self) {
+        self.latency_recorder = Some(LatencyRecorder::default());
+    }
+
+    pub fn latency_recorder(&self) -> Option<&LatencyRecorder> {
+        self.latency_recorder.as_ref()
+    }
+
+    pub fn enable_output_recording(&mut self) {
+        self.output_recording = Some(OutputRecording::new(Instant::now()));
+    }
+
+    pub fn output_recording(&self) -> Option<&OutputRecording> {
+        self.output_recording.as_ref()
+    }
+
+    pub fn output_recording_mut(&mut self) -> Option<&mut OutputRecording> {
+        self.output_recording.as_mut()
+    }
+
+    // The keyboard stays in control until the playback is started with the
+    // playback hotkey or the playback control command.
+    pub fn arm_playback(&mut self, playback: Playback) {
+        self.playback = Some(playback);
+    }
+
+    // The keyboard is ignored until the playback finishes or is stopped.
+    pub fn start_playback(&mut self) -> Result<(), String> {
+        let playback = self.playback.as_mut().ok_or_else(|| "No playback is loaded.".to_string())?;
+        playback.start(Instant::now());
+        self.controller.reset_to_neutral();
+        print_message(format!("Playing back {} frames.", playback.frame_count()));
+        Ok(())
+    }
+
+    pub fn stop_playback(&mut self) {
+        if let Some(playback) = &mut self.playback {
+            if playback.is_started() {
+                playback.stop();
+                self.controller.reset_to_neutral();
+                print_message("Playback stopped, the keyboard is back in control.".to_string());
+            }
+        }
+    }
+
+    // Whether an armed playback can be started later, rather than right away.
+    pub fn has_playback_trigger(&self) -> bool {
+        self.config.playback_hotkey.is_some() || self.control_server.is_some()
+    }
+
+    pub fn is_playing_back(&self) -> bool {
+        self.playback.as_ref().is_some_and(Playback::is_started)
+    }
+
+    fn update_controller_state_with_playback(&mut self) {
+        let frame = self.playback.as_ref().and_then(|playback| playback.frame_at(Instant::now()));
+        match frame {
+            Some(frame) => self.controller.controller_state.set_from_snapshot(&frame),
+            None => {
+                self.stop_playback();
+                print_message("Playback finished, it can be started again.".to_string());
+            },
+        }
+    }
+
+    fn handle_playback_hotkey(&mut self, event: &KeyEvent) {
+        let is_hotkey_press = event.is_pressed && self.config.playback_hotkey
+            .is_some_and(|key_bind| key_bind_matches_event(key_bind, event));
+        if !is_hotkey_press {
+            return;
+        }
+        if self.is_playing_back() {
+            self.stop_playback();
+        }
+        else if let Err(e) = self.start_playback() {
+            print_message(e);
+        }
+    }
+
+    // Without a feed the techniques go back to guessing from the keys, and the
+    // preset goes back to the character in the config.
+    fn update_game_state(&mut self) {
+        let game_state = self.game_state_feed.as_ref().and_then(|feed| feed.game_state());
+        let character = game_state
+            .map(|game_state| game_state.character)
+            .filter(|character| *character != Character::Generic)
+            .unwrap_or(self.config.character);
+        if character != self.character {
+            self.set_character(character);
+        }
+        self.controller.set_game_state(game_state);
+    }
+
+    fn handle_control_requests(&mut self) {
+        loop {
+            let request = match self.control_server.as_ref().and_then(|server| server.try_recv()) {
+                Some(request) => request,
+                None => return,
+            };
+            let reply = self.handle_control_command(&request.command);
+            request.respond(reply);
+        }
+    }
+
+    pub fn handle_control_command(&mut self, command: &ControlCommand) -> String {
+        let result = match command {
+            ControlCommand::SwitchProfile(profile) => self.switch_profile(profile.clone()),
+            ControlCommand::SetToggle(toggle, value) => {
+                self.set_toggle(*toggle, *value);
+                Ok(())
+            },
+            ControlCommand::SetPaused(value) => {
+                keyboard_input::set_paused(value.unwrap_or(!keyboard_input::is_paused()));
+                Ok(())
+            },
+            ControlCommand::QueryState => Ok(()),
+            ControlCommand::ReloadConfig => self.switch_profile(self.profile.clone()),
+            ControlCommand::StartPlayback(file) => {
+                let loaded = match file {
+                    Some(file) => Playback::load(file).map(|playback| self.arm_playback(playback)),
+                    None => Ok(()),
+                };
+                loaded.and_then(|()| self.start_playback())
+            },
+            ControlCommand::StopPlayback => {
+                self.stop_playback();
+                Ok(())
+            },
+        };
+        match result {
+            Ok(()) => serde_json::json!({ "ok": true, "state": self.control_state() }).to_string(),
+            Err(e) => serde_json::json!({ "ok": false, "error": e }).to_string(),
+        }
+    }
+
+    fn control_state(&self) -> serde_json::Value {
+        serde_json::json!({
+            "profile": self.profile.as_deref().unwrap_or("default"),
+            "is_paused": keyboard_input::is_paused(),
+            "input_backend_health": format!("{:?}", self.input_backend_health),
+            "game_state": self.controller.game_state(),
+            "is_playing_back": self.is_playing_back(),
+            "use_short_hop_macro": self.config.use_short_hop_macro,
+            "use_c_stick_tilting": self.config.use_c_stick_tilting,
+            "use_extra_b_buttons": self.config.use_extra_b_buttons,
+            "character": self.character,
+            "held_actions": self.controller.held_actions(),
+            "controller": self.controller.controller_state.snapshot(),
+        })
+    }
+
+    // Profiles are config files in the profiles folder, e.g. profiles/fox.json.
+    // None switches back to config.json.
+    pub fn switch_profile(&mut self, profile: Option<String>) -> Result<(), String> {
+        let file_name = match &profile {
+            Some(name) => format!("profiles/{}.json", name),
+            None => "config.json".to_string(),
+        };
+        let mut config = KeyboardMeleeControllerConfig::try_load(&file_name)?;
+        let ignored_settings = config.keep_startup_settings(&self.config);
+        if !ignored_settings.is_empty() {
+            print_message(format!("{} changes {}, which only take effect after a restart.", file_name, ignored_settings.join(", ")));
+        }
+
+        self.config = config;
+        self.profile = profile;
+        self.controller.reset_to_neutral();
+        self.apply_config();
+        print_message(format!("Loaded {}.", file_name));
+        Ok(())
+    }
+
+    // None flips the current value.
+    pub fn set_toggle(&mut self, toggle: ControllerToggle, value: Option<bool>) {
+        let setting = match toggle {
+            ControllerToggle::ShortHopMacro => &mut self.config.use_short_hop_macro,
+            ControllerToggle::CStickTilting => &mut self.config.use_c_stick_tilting,
+            ControllerToggle::ExtraBButtons => &mut self.config.use_extra_b_buttons,
+        };
+        *setting = value.unwrap_or(!*setting);
+        self.controller.set_use_short_hop_macro(self.config.use_short_hop_macro);
+        self.controller.set_use_c_stick_tilting(self.config.use_c_stick_tilting);
+        self.controller.set_use_extra_b_buttons(self.config.use_extra_b_buttons);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    pub fn input_backend_health(&self) -> InputBackendHealth {
+        self.input_backend_health
+    }
+
+    pub fn scheduler_settings(&self) -> SchedulerSettings {
+        self.config.scheduler
+    }
+
+    // Leaves things the way they were found: the output at neutral, every key
+    // unblocked and the vJoy device free for other programs.
+    pub fn shutdown(&mut self) {
+        if self.is_shut_down {
+            return;
+        }
+        self.is_shut_down = true;
+        keyboard_input::unblock_all_keys();
+        keyboard_input::stop_hook();
+        self.controller.reset_to_neutral();
+        self.update_vjoy_device_outputs();
+        if let Some(vjoy_device) = &mut self.vjoy_device {
+            if let Err(e) = vjoy_device.send_inputs() {
+                print_message(format!("Could not set vJoy device {} to neutral: {}", vjoy_device.device_id(), e));
+            }
+            vjoy_device.relinquish();
+        }
+    }
+
+    fn update_vjoy_device_outputs(&mut self) {
         self.update_vjoy_device_buttons();
+        self.update_vjoy_device_pov();
         self.update_vjoy_device_axes();
         self.update_vjoy_device_sliders();
-        self.vjoy_device.send_inputs();
+    }
+
+    // The output is held at neutral while there is no keyboard input, otherwise
+    // whatever was held when it was lost would stay held.
+    fn update_input_backend_health(&mut self) {
+        let health = keyboard_input::input_backend_health();
+        if health == self.input_backend_health {
+            return;
+        }
+        let was_disconnected = self.input_backend_health == InputBackendHealth::Disconnected;
+        self.input_backend_health = health;
+        if health == InputBackendHealth::Disconnected {
+            self.controller.reset_to_neutral();
+            self.update_vjoy_device_outputs();
+            print_message("Lost keyboard input, holding the controller at neutral.".to_string());
+        }
+        else if was_disconnected {
+            self.controller.reset_to_neutral();
+            print_message("Keyboard input connected.".to_string());
+        }
+    }
+
+    // The output is held at neutral for as long as the controller is paused.
+    fn update_pause_state(&mut self) {
+        let is_paused = keyboard_input::is_paused();
+        if is_paused == self.is_paused {
+            return;
+        }
+        self.is_paused = is_paused;
+        self.controller.reset_to_neutral();
+        self.update_vjoy_device_outputs();
+        if is_paused {
+            print_message("Paused, keys are passed through.".to_string());
+        }
+        else {
+            print_message("Resumed.".to_string());
+        }
+    }
+
+    // Only report when the device is lost or regained so the console isn't flooded every tick.
+    fn send_vjoy_device_inputs(&mut self) -> bool {
+        if crate::shutdown::neutral_requested() {
+            self.controller.reset_to_neutral();
+            self.update_vjoy_device_outputs();
+        }
+        let vjoy_device = match &mut self.vjoy_device {
+            Some(vjoy_device) => vjoy_device,
+            None => return true,
+        };
+        match vjoy_device.send_inputs() {
+            Ok(()) => {
+                if self.is_vjoy_device_lost {
+                    self.is_vjoy_device_lost = false;
+                    print_message(format!("Reacquired vJoy device {}.", vjoy_device.device_id()));
+                }
+                true
+            },
+            // Only the error that lost the device is shown, retries fail in different ways until it is back.
+            Err(e) => {
+                if !self.is_vjoy_device_lost {
+                    self.is_vjoy_device_lost = true;
+                    print_message(format!("{} Retrying...", e));
+                }
+                false
+            },
+        }
+    }
+
+    // Events are applied in the order they happened. A key released in the same tick
+    // it was pressed, or pressed again in the same tick it was released, holds up the
+    // rest of the events until the next tick, so short taps and quick re-presses are
+    // always seen by the controller.
+    fn apply_key_events_for_tick(&mut self) {
+        while let Some(event) = keyboard_input::pop_key_event() {
+            self.pending_key_events.push_back(event);
+        }
+        let mut keys_pressed_this_tick = Vec::new();
+        let mut keys_released_this_tick = Vec::new();
+        while let Some(event) = self.pending_key_events.front() {
+            let key = (event.key, event.scan_code);
+            if event.is_pressed && keys_released_this_tick.contains(&key)
+            || !event.is_pressed && keys_pressed_this_tick.contains(&key) {
+                break;
+            }
+            if event.is_pressed {
+                keys_pressed_this_tick.push(key);
+            }
+            else {
+                keys_released_this_tick.push(key);
+            }
+            let event = *event;
+            self.key_state.apply(&event);
+            self.pending_key_events.pop_front();
+            self.record_key_event_latency(&event);
+            self.handle_playback_hotkey(&event);
+        }
+    }
+
+    fn record_key_event_latency(&mut self, event: &KeyEvent) {
+        let recorder = match &mut self.latency_recorder {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        for (action, key_binds) in &self.config.key_binds {
+            if key_binds.iter().any(|key_bind| key_bind_matches_event(*key_bind, event)) {
+                recorder.record_action_change(*action, event.time);
+            }
+        }
+    }
+
+    fn apply_all_key_events(&mut self) {
+        while let Some(event) = keyboard_input::pop_key_event() {
+            self.pending_key_events.push_back(event);
+        }
+        if let Some(recorder) = &mut self.latency_recorder {
+            recorder.forget_pending_actions();
+        }
+        while let Some(event) = self.pending_key_events.pop_front() {
+            self.key_state.apply(&event);
+            if self.is_playing_back() {
+                self.handle_playback_hotkey(&event);
+            }
+        }
     }
 
     fn update_controller_state_with_keys(&mut self) {
@@ -53,7 +569,7 @@ impl KeyboardMeleeController {
                 {
                     let mut state = false;
                     for keybind in &*keybinds {
-                        if keyboard_input::key_is_pressed(*keybind) {
+                        if self.key_state.key_bind_is_pressed(*keybind) {
                             state = true;
                         }
                     }
@@ -65,37 +581,186 @@ impl KeyboardMeleeController {
     }
 
     fn update_vjoy_device_buttons(&mut self) {
+        let vjoy_device = match &mut self.vjoy_device {
+            Some(vjoy_device) => vjoy_device,
+            None => return,
+        };
         for (button_variant, button_id) in &self.config.vjoy_button_binds {
+            if self.config.vjoy_d_pad_output != DPadOutput::Buttons && is_d_pad_button(*button_variant) {
+                continue;
+            }
             let button = self.controller.controller_state.button(*button_variant);
-            self.vjoy_device.set_button(*button_id, button.is_pressed());
+            vjoy_device.set_button(*button_id, button.is_pressed());
+        }
+    }
+
+    fn update_vjoy_device_pov(&mut self) {
+        let vjoy_device = match &mut self.vjoy_device {
+            Some(vjoy_device) => vjoy_device,
+            None => return,
+        };
+        let state = &self.controller.controller_state;
+        let left = state.d_left_button.is_pressed();
+        let right = state.d_right_button.is_pressed();
+        let down = state.d_down_button.is_pressed();
+        let up = state.d_up_button.is_pressed();
+        let pov_id = self.config.vjoy_d_pad_pov_id;
+
+        match self.config.vjoy_d_pad_output {
+            DPadOutput::Buttons => (),
+            DPadOutput::DiscretePov => {
+                let direction = if up { Some(VJoyPovDirection::North) }
+                                else if right { Some(VJoyPovDirection::East) }
+                                else if down { Some(VJoyPovDirection::South) }
+                                else if left { Some(VJoyPovDirection::West) }
+                                else { None };
+                vjoy_device.set_discrete_pov(pov_id, direction);
+            },
+            DPadOutput::ContinuousPov => {
+                let x = (right as i32) - (left as i32);
+                let y = (up as i32) - (down as i32);
+                let angle = match (x, y) {
+                    (0, 1) => Some(0),
+                    (1, 1) => Some(4500),
+                    (1, 0) => Some(9000),
+                    (1, -1) => Some(13500),
+                    (0, -1) => Some(18000),
+                    (-1, -1) => Some(22500),
+                    (-1, 0) => Some(27000),
+                    (-1, 1) => Some(31500),
+                    _ => None,
+                };
+                vjoy_device.set_continuous_pov(pov_id, angle);
+            },
         }
     }
 
     fn update_vjoy_device_axes(&mut self) {
+        let vjoy_device = match &mut self.vjoy_device {
+            Some(vjoy_device) => vjoy_device,
+            None => return,
+        };
         for (axis_variant, vjoy_axis) in &self.config.vjoy_axis_binds {
             let axis = self.controller.controller_state.axis(*axis_variant);
-            self.vjoy_device.set_axis(*vjoy_axis, axis.value());
+            vjoy_device.set_axis(*vjoy_axis, axis.value());
         }
     }
 
     fn update_vjoy_device_sliders(&mut self) {
+        let vjoy_device = match &mut self.vjoy_device {
+            Some(vjoy_device) => vjoy_device,
+            None => return,
+        };
         for (slider_variant, vjoy_slider) in &self.config.vjoy_slider_binds {
             let slider = self.controller.controller_state.slider(*slider_variant);
-            self.vjoy_device.set_axis(*vjoy_slider, slider.value());
+            vjoy_device.set_slider(*vjoy_slider, slider.value());
         }
     }
 }
 
-type KeyBinds = HashMap<Action, Vec<KeyboardKey>>;
+impl Drop for KeyboardMeleeController {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn select_keyboard_device(selector: &Option<KeyboardDeviceSelector>) {
+    let selector = match selector {
+        Some(selector) => selector,
+        None => {
+            keyboard_input::set_keyboard_device(None);
+            return;
+        },
+    };
+    if !keyboard_input::supports_keyboard_device_selection() {
+        print_message("keyboard_device in config.json is not supported on this platform, reading every keyboard.".to_string());
+        return;
+    }
+    keyboard_input::set_keyboard_device(Some(selector.clone()));
+
+    let devices = keyboard_input::keyboard_devices();
+    if devices.iter().any(|device| selector.matches(device)) {
+        return;
+    }
+    print_message("No keyboard matches keyboard_device in config.json, waiting for it to be plugged in. Keyboards found:".to_string());
+    for device in &devices {
+        print_message(format!(
+            "  Name: \"{}\", Path: \"{}\", Physical path: \"{}\", Id: {:04x}:{:04x}",
+            device.name, device.path, device.physical_path, device.vendor_id, device.product_id,
+        ));
+    }
+}
+
+fn key_bind_matches_event(key_bind: KeyBind, event: &KeyEvent) -> bool {
+    match key_bind {
+        KeyBind::Key(key) => key == event.key,
+        KeyBind::Physical(PhysicalKey::ScanCode(scan_code)) => scan_code == event.scan_code,
+    }
+}
+
+fn is_d_pad_button(button: GameCubeControllerButton) -> bool {
+    matches!(button, GameCubeControllerButton::DLeft
+                   | GameCubeControllerButton::DRight
+                   | GameCubeControllerButton::DDown
+                   | GameCubeControllerButton::DUp)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+enum KeyBlocking {
+    Nothing,
+    BoundKeys,
+    AllKeys,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+enum DPadOutput {
+    Buttons,
+    DiscretePov,
+    ContinuousPov,
+}
+
+type KeyBinds = HashMap<Action, Vec<KeyBind>>;
+
+// Moves every virtual key bind to where that key is on a US QWERTY keyboard.
+fn physical_key_binds(binds: &KeyBinds) -> KeyBinds {
+    binds.iter()
+        .map(|(action, key_binds)| {
+            (*action, key_binds.iter().map(|key_bind| key_bind.to_physical()).collect())
+        })
+        .collect()
+}
 type VJoyButtonBinds = HashMap<GameCubeControllerButton, u32>;
 type VJoyAxisBinds = HashMap<GameCubeControllerAxis, VJoyAxis>;
 type VJoySliderBinds = HashMap<GameCubeControllerSlider, VJoyAxis>;
 
 #[derive(Serialize, Deserialize)]
 struct KeyboardMeleeControllerConfig {
+    // Configs from before there was a version are 0.
+    #[serde(default)]
+    pub config_version: u32,
+
     #[serde(default = "KeyboardMeleeControllerConfig::default_key_binds")]
     pub key_binds: KeyBinds,
 
+    #[serde(default)]
+    pub use_physical_key_binds: bool,
+
+    #[serde(default)]
+    pub keyboard_device: Option<KeyboardDeviceSelector>,
+
+    #[serde(default = "KeyboardMeleeControllerConfig::default_key_blocking")]
+    pub key_blocking: KeyBlocking,
+
+    #[serde(default)]
+    pub passthrough_keys: Vec<KeyBind>,
+
+    #[serde(default = "KeyboardMeleeControllerConfig::default_pause_hotkey")]
+    pub pause_hotkey: Option<KeyBind>,
+
+    // Starts and stops a playback loaded with --playback or the control server.
+    #[serde(default)]
+    pub playback_hotkey: Option<KeyBind>,
+
     #[serde(default = "KeyboardMeleeControllerConfig::default_use_short_hop_macro")]
     pub use_short_hop_macro: bool,
 
@@ -105,6 +770,29 @@ struct KeyboardMeleeControllerConfig {
     #[serde(default = "KeyboardMeleeControllerConfig::default_use_extra_b_buttons")]
     pub use_extra_b_buttons: bool,
 
+    // Sets the jumpsquat used for full hops, unless the game state feed says who is being played. The overrides can also give this character its own
+    // short hop release frame, air dodge levels and tilt levels instead of the ones in technique.
+    #[serde(default)]
+    pub character: Character,
+
+    #[serde(default)]
+    pub character_overrides: CharacterPresetOverrides,
+
+    #[serde(default)]
+    pub technique: TechniqueSettings,
+
+    #[serde(default)]
+    pub scheduler: SchedulerSettings,
+
+    #[serde(default)]
+    pub overlay_server: OverlayServerSettings,
+
+    #[serde(default)]
+    pub control_server: ControlServerSettings,
+
+    #[serde(default)]
+    pub game_state_feed: GameStateFeedSettings,
+
     #[serde(default = "KeyboardMeleeControllerConfig::default_vjoy_dll_path")]
     pub vjoy_dll_path: String,
 
@@ -114,6 +802,12 @@ struct KeyboardMeleeControllerConfig {
     #[serde(default = "KeyboardMeleeControllerConfig::default_vjoy_button_binds")]
     pub vjoy_button_binds: VJoyButtonBinds,
 
+    #[serde(default = "KeyboardMeleeControllerConfig::default_vjoy_d_pad_output")]
+    pub vjoy_d_pad_output: DPadOutput,
+
+    #[serde(default = "KeyboardMeleeControllerConfig::default_vjoy_d_pad_pov_id")]
+    pub vjoy_d_pad_pov_id: u32,
+
     #[serde(default = "KeyboardMeleeControllerConfig::default_vjoy_axis_binds")]
     pub vjoy_axis_binds: VJoyAxisBinds,
 
@@ -121,6 +815,29 @@ struct KeyboardMeleeControllerConfig {
     pub vjoy_slider_binds: VJoySliderBinds,
 }
 
+// Bumped whenever an older config has to be changed to keep meaning the same thing.
+// 1: F20 to F24 moved up one keycode to make room for F19.
+const CONFIG_VERSION: u32 = 1;
+
+// F20 to F24 used to be one keycode too low, so an old F20 bind was really the F19 key
+// and so on. The real F24 had no name and was saved by its keycode.
+fn renumbered_function_key(key_bind: KeyBind) -> Option<KeyBind> {
+    let key = match key_bind {
+        KeyBind::Key(key) => key,
+        KeyBind::Physical(_) => return None,
+    };
+    let moved_key = match key {
+        KeyboardKey::F20 => KeyboardKey::F19,
+        KeyboardKey::F21 => KeyboardKey::F20,
+        KeyboardKey::F22 => KeyboardKey::F21,
+        KeyboardKey::F23 => KeyboardKey::F22,
+        KeyboardKey::F24 => KeyboardKey::F23,
+        KeyboardKey::Unknown(135) => KeyboardKey::F24,
+        _ => return None,
+    };
+    Some(KeyBind::Key(moved_key))
+}
+
 impl KeyboardMeleeControllerConfig {
     fn save(&self, file_name: &str) -> std::io::Result<()> {
         let mut config = File::create(file_name)?;
@@ -133,10 +850,13 @@ impl KeyboardMeleeControllerConfig {
         let output_config: KeyboardMeleeControllerConfig;
 
         if let Ok(config_string) = fs::read_to_string(file_name) {
-            match serde_json::from_str(&config_string) {
-                Ok(value) => output_config = value,
+            match serde_json::from_str::<KeyboardMeleeControllerConfig>(&config_string) {
+                Ok(mut value) => {
+                    value.migrate(file_name);
+                    output_config = value;
+                },
                 Err(e) => {
-                    println!("Could not parse config.json, loading default config:\n {}", e);
+                    print_message(format!("Could not parse config.json, loading default config:\n {}", e));
                     output_config = KeyboardMeleeControllerConfig::default();
                 }
             }
@@ -144,51 +864,126 @@ impl KeyboardMeleeControllerConfig {
         else {
             output_config = KeyboardMeleeControllerConfig::default();
             if let Err(e) = output_config.save("config.json") {
-                println!("Could not save config.json:\n {}", e);
+                print_message(format!("Could not save config.json:\n {}", e));
             }
         }
 
         output_config
     }
 
+    fn try_load(file_name: &str) -> Result<KeyboardMeleeControllerConfig, String> {
+        let config_string = fs::read_to_string(file_name)
+            .map_err(|e| format!("Could not read {}: {}", file_name, e))?;
+        let mut config: KeyboardMeleeControllerConfig = serde_json::from_str(&config_string)
+            .map_err(|e| format!("Could not parse {}: {}", file_name, e))?;
+        config.migrate(file_name);
+        Ok(config)
+    }
+
+    // Saved back with the new version, so each config is only ever migrated once.
+    fn migrate(&mut self, file_name: &str) {
+        if self.config_version >= CONFIG_VERSION {
+            return;
+        }
+        let key_binds = self.key_binds.values_mut().flatten()
+            .chain(self.passthrough_keys.iter_mut())
+            .chain(self.pause_hotkey.iter_mut())
+            .chain(self.playback_hotkey.iter_mut());
+        let mut moved_key_count = 0;
+        for key_bind in key_binds {
+            if let Some(moved_key_bind) = renumbered_function_key(*key_bind) {
+                *key_bind = moved_key_bind;
+                moved_key_count += 1;
+            }
+        }
+        if moved_key_count > 0 {
+            print_message(format!(
+                "Moved {} binds in {} down one key, F20 to F19 and so on. Older versions numbered F20 to F24 one keycode too low.",
+                moved_key_count, file_name,
+            ));
+        }
+
+        self.config_version = CONFIG_VERSION;
+        if let Err(e) = self.save(file_name) {
+            print_message(format!("Could not save {}:\n {}", file_name, e));
+        }
+    }
+
+    // The vJoy device, the servers, the game state feed and the scheduler are set up once at startup,
+    // so those settings are kept from the running config. Returns the ones that differed.
+    fn keep_startup_settings(&mut self, running: &KeyboardMeleeControllerConfig) -> Vec<&'static str> {
+        let mut ignored_settings = Vec::new();
+        if self.vjoy_dll_path != running.vjoy_dll_path {
+            ignored_settings.push("vjoy_dll_path");
+            self.vjoy_dll_path = running.vjoy_dll_path.clone();
+        }
+        if self.vjoy_device_id != running.vjoy_device_id {
+            ignored_settings.push("vjoy_device_id");
+            self.vjoy_device_id = running.vjoy_device_id;
+        }
+        if self.scheduler != running.scheduler {
+            ignored_settings.push("scheduler");
+            self.scheduler = running.scheduler;
+        }
+        if self.overlay_server != running.overlay_server {
+            ignored_settings.push("overlay_server");
+            self.overlay_server = running.overlay_server.clone();
+        }
+        if self.control_server != running.control_server {
+            ignored_settings.push("control_server");
+            self.control_server = running.control_server.clone();
+        }
+        if self.game_state_feed != running.game_state_feed {
+            ignored_settings.push("game_state_feed");
+            self.game_state_feed = running.game_state_feed.clone();
+        }
+        ignored_settings
+    }
+
+    fn default_key_blocking() -> KeyBlocking { KeyBlocking::AllKeys }
+
+    fn default_pause_hotkey() -> Option<KeyBind> { Some(KeyboardKey::Pause.into()) }
+
     fn default_use_short_hop_macro() -> bool { true }
     fn default_use_c_stick_tilting() -> bool { true }
     fn default_use_extra_b_buttons() -> bool { true }
 
     fn default_vjoy_device_id() -> u32 { 1 }
+    fn default_vjoy_d_pad_output() -> DPadOutput { DPadOutput::Buttons }
+    fn default_vjoy_d_pad_pov_id() -> u32 { 1 }
     fn default_vjoy_dll_path() -> String { "C:\\Program Files\\vJoy\\x64\\vJoyInterface.dll".to_string() }
 
     fn default_key_binds() -> KeyBinds {
         let mut binds = HashMap::new();
-        binds.insert(Action::Left, vec![KeyboardKey::A]);
-        binds.insert(Action::Right, vec![KeyboardKey::D]);
-        binds.insert(Action::Down, vec![KeyboardKey::S]);
-        binds.insert(Action::Up, vec![KeyboardKey::W]);
-        binds.insert(Action::XMod, vec![KeyboardKey::LeftAlt]);
-        binds.insert(Action::YMod, vec![KeyboardKey::Space]);
-        binds.insert(Action::Tilt, vec![KeyboardKey::CapsLock]);
-        binds.insert(Action::CLeft, vec![KeyboardKey::L]);
-        binds.insert(Action::CRight, vec![KeyboardKey::Slash]);
-        binds.insert(Action::CDown, vec![KeyboardKey::Apostrophe]);
-        binds.insert(Action::CUp, vec![KeyboardKey::P]);
-        binds.insert(Action::ShortHop, vec![KeyboardKey::LeftBracket,
-                                            KeyboardKey::Minus]);
-        binds.insert(Action::FullHop, vec![KeyboardKey::BackSlash]);
-        binds.insert(Action::A, vec![KeyboardKey::RightWindows]);
-        binds.insert(Action::B, vec![KeyboardKey::RightAlt]);
-        binds.insert(Action::BUp, vec![KeyboardKey::Period]);
-        binds.insert(Action::BSide, vec![KeyboardKey::Backspace]);
-        binds.insert(Action::Z, vec![KeyboardKey::Equals]);
-        binds.insert(Action::Shield, vec![KeyboardKey::RightBracket]);
-        binds.insert(Action::ToggleLightShield, vec![KeyboardKey::Space]);
-        binds.insert(Action::AirDodge, vec![KeyboardKey::Semicolon]);
-        binds.insert(Action::Start, vec![KeyboardKey::Key5]);
-        binds.insert(Action::DLeft, vec![KeyboardKey::V]);
-        binds.insert(Action::DRight, vec![KeyboardKey::N]);
-        binds.insert(Action::DDown, vec![KeyboardKey::B]);
-        binds.insert(Action::DUp, vec![KeyboardKey::G]);
-        binds.insert(Action::ChargeSmash, vec![KeyboardKey::Space]);
-        binds.insert(Action::InvertXAxis, vec![KeyboardKey::Enter]);
+        binds.insert(Action::Left, vec![KeyboardKey::A.into()]);
+        binds.insert(Action::Right, vec![KeyboardKey::D.into()]);
+        binds.insert(Action::Down, vec![KeyboardKey::S.into()]);
+        binds.insert(Action::Up, vec![KeyboardKey::W.into()]);
+        binds.insert(Action::XMod, vec![KeyboardKey::LeftAlt.into()]);
+        binds.insert(Action::YMod, vec![KeyboardKey::Space.into()]);
+        binds.insert(Action::Tilt, vec![KeyboardKey::CapsLock.into()]);
+        binds.insert(Action::CLeft, vec![KeyboardKey::L.into()]);
+        binds.insert(Action::CRight, vec![KeyboardKey::Slash.into()]);
+        binds.insert(Action::CDown, vec![KeyboardKey::Apostrophe.into()]);
+        binds.insert(Action::CUp, vec![KeyboardKey::P.into()]);
+        binds.insert(Action::ShortHop, vec![KeyboardKey::LeftBracket.into(),
+                                            KeyboardKey::Minus.into()]);
+        binds.insert(Action::FullHop, vec![KeyboardKey::BackSlash.into()]);
+        binds.insert(Action::A, vec![KeyboardKey::RightWindows.into()]);
+        binds.insert(Action::B, vec![KeyboardKey::RightAlt.into()]);
+        binds.insert(Action::BUp, vec![KeyboardKey::Period.into()]);
+        binds.insert(Action::BSide, vec![KeyboardKey::Backspace.into()]);
+        binds.insert(Action::Z, vec![KeyboardKey::Equals.into()]);
+        binds.insert(Action::Shield, vec![KeyboardKey::RightBracket.into()]);
+        binds.insert(Action::ToggleLightShield, vec![KeyboardKey::Space.into()]);
+        binds.insert(Action::AirDodge, vec![KeyboardKey::Semicolon.into()]);
+        binds.insert(Action::Start, vec![KeyboardKey::Key5.into()]);
+        binds.insert(Action::DLeft, vec![KeyboardKey::V.into()]);
+        binds.insert(Action::DRight, vec![KeyboardKey::N.into()]);
+        binds.insert(Action::DDown, vec![KeyboardKey::B.into()]);
+        binds.insert(Action::DUp, vec![KeyboardKey::G.into()]);
+        binds.insert(Action::ChargeSmash, vec![KeyboardKey::Space.into()]);
+        binds.insert(Action::InvertXAxis, vec![KeyboardKey::Enter.into()]);
         binds
     }
 
@@ -221,6 +1016,7 @@ impl KeyboardMeleeControllerConfig {
     fn default_vjoy_slider_binds() -> VJoySliderBinds {
         let mut binds = HashMap::new();
         binds.insert(GameCubeControllerSlider::L, VJoyAxis::Slider0);
+        binds.insert(GameCubeControllerSlider::R, VJoyAxis::Slider1);
         binds
     }
 }
@@ -228,13 +1024,29 @@ impl KeyboardMeleeControllerConfig {
 impl Default for KeyboardMeleeControllerConfig {
     fn default() -> Self {
         Self {
+            config_version: CONFIG_VERSION,
             key_binds: KeyboardMeleeControllerConfig::default_key_binds(),
+            use_physical_key_binds: false,
+            keyboard_device: None,
+            key_blocking: KeyboardMeleeControllerConfig::default_key_blocking(),
+            passthrough_keys: Vec::new(),
+            pause_hotkey: KeyboardMeleeControllerConfig::default_pause_hotkey(),
+            playback_hotkey: None,
             use_short_hop_macro: KeyboardMeleeControllerConfig::default_use_short_hop_macro(),
             use_c_stick_tilting: KeyboardMeleeControllerConfig::default_use_c_stick_tilting(),
             use_extra_b_buttons: KeyboardMeleeControllerConfig::default_use_extra_b_buttons(),
+            character: Default::default(),
+            character_overrides: Default::default(),
+            technique: Default::default(),
+            scheduler: Default::default(),
+            overlay_server: Default::default(),
+            control_server: Default::default(),
+            game_state_feed: Default::default(),
             vjoy_dll_path: KeyboardMeleeControllerConfig::default_vjoy_dll_path(),
             vjoy_device_id: KeyboardMeleeControllerConfig::default_vjoy_device_id(),
             vjoy_button_binds: KeyboardMeleeControllerConfig::default_vjoy_button_binds(),
+            vjoy_d_pad_output: KeyboardMeleeControllerConfig::default_vjoy_d_pad_output(),
+            vjoy_d_pad_pov_id: KeyboardMeleeControllerConfig::default_vjoy_d_pad_pov_id(),
             vjoy_axis_binds: KeyboardMeleeControllerConfig::default_vjoy_axis_binds(),
             vjoy_slider_binds: KeyboardMeleeControllerConfig::default_vjoy_slider_binds(),
         }
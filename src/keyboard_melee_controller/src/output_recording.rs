@@ -0,0 +1,55 @@
+use std::time::{Duration, Instant};
+
+use digital_melee_controller::GameCubeControllerSnapshot;
+
+// Every output that was sent, stored only when it changes.
+pub struct OutputRecording {
+    start: Instant,
+    changes: Vec<(Duration, GameCubeControllerSnapshot)>,
+    end: Option<Duration>,
+}
+
+impl OutputRecording {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            changes: Vec::new(),
+            end: None,
+        }
+    }
+
+    pub fn record(&mut self, time: Instant, output: &GameCubeControllerSnapshot) {
+        if self.changes.last().is_some_and(|(_, last_output)| last_output == output) {
+            return;
+        }
+        self.changes.push((time.saturating_duration_since(self.start), *output));
+    }
+
+    // Holding the last output for a while is part of the recording too, so it
+    // lasts until it is finished rather than until the last change.
+    pub fn finish(&mut self, time: Instant) {
+        self.end = Some(time.saturating_duration_since(self.start));
+    }
+
+    pub fn duration(&self) -> Duration {
+        let last_change = self.changes.last().map(|(time, _)| *time).unwrap_or_default();
+        self.end.map_or(last_change, |end| end.max(last_change))
+    }
+
+    // The output as the console would have seen it polling once per frame,
+    // starting when the recording started.
+    pub fn frames(&self, frame_rate: f64) -> Vec<GameCubeControllerSnapshot> {
+        let frame_count = (self.duration().as_secs_f64() * frame_rate).ceil() as usize + 1;
+        let mut frames = Vec::with_capacity(frame_count);
+        let mut output = GameCubeControllerSnapshot::default();
+        let mut changes = self.changes.iter().peekable();
+        for frame in 0..frame_count {
+            let frame_time = Duration::from_secs_f64(frame as f64 / frame_rate);
+            while let Some((_, next_output)) = changes.next_if(|(time, _)| *time <= frame_time) {
+                output = *next_output;
+            }
+            frames.push(output);
+        }
+        frames
+    }
+}
@@ -0,0 +1,251 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Deserialize};
+use tungstenite::Message;
+use tungstenite::handshake::server::{Request, Response, ErrorResponse};
+use tungstenite::http::StatusCode;
+use tungstenite::http::header::ORIGIN;
+use digital_melee_controller::{Action, GameCubeControllerSnapshot};
+use keyboard_input::print_message;
+
+const OVERLAY_PAGE: &str = include_str!("overlay.html");
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+const CLIENT_WAIT_TIMEOUT: Duration = Duration::from_millis(1000);
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(1000);
+const REQUEST_PEEK_SIZE: usize = 4096;
+const REQUEST_PEEK_INTERVAL: Duration = Duration::from_millis(5);
+const CLOSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverlayServerSettings {
+    pub enabled: bool,
+    // Anything other than a loopback address lets other machines on the network connect.
+    pub address: String,
+    // Web pages that may read the feed, like "https://example.com". Pages served by the
+    // overlay server itself always can, and so can anything that isn't a browser.
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for OverlayServerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:8765".to_string(),
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OverlayMessage<'a> {
+    controller: &'a GameCubeControllerSnapshot,
+    actions: &'a [Action],
+    is_paused: bool,
+}
+
+// The latest message and a count of how many have been published. Clients send
+// whatever is newest when they get to it, so a slow client skips states rather
+// than falling further and further behind.
+struct Feed {
+    latest: Mutex<(u64, String)>,
+    changed: Condvar,
+    is_stopping: AtomicBool,
+    // Browsers let any page connect to a WebSocket, even on this machine, so only these
+    // origins get the feed.
+    allowed_origins: Vec<String>,
+}
+
+// Serves the overlay page over HTTP and the controller state as JSON over a
+// WebSocket, both on the same port. OBS can use http://127.0.0.1:8765/ as a browser source.
+pub struct OverlayServer {
+    feed: Arc<Feed>,
+    last_output: Option<GameCubeControllerSnapshot>,
+    last_actions: Vec<Action>,
+    last_is_paused: bool,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl OverlayServer {
+    pub fn start(settings: &OverlayServerSettings) -> io::Result<Self> {
+        let address: SocketAddr = settings.address.parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("\"{}\" is not an address ({})", settings.address, e)))?;
+        if !address.ip().is_loopback() {
+            print_message(format!("The overlay server is listening on {}, which other machines can reach.", address));
+        }
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        let feed = Arc::new(Feed {
+            latest: Mutex::new((0, String::new())),
+            changed: Condvar::new(),
+            is_stopping: AtomicBool::new(false),
+            allowed_origins: allowed_origins(address, &settings.allowed_origins),
+        });
+        let thread_feed = feed.clone();
+        let accept_thread = thread::spawn(move || accept_connections(listener, thread_feed));
+        print_message(format!("Overlay available at http://{}/", address));
+
+        Ok(Self {
+            feed,
+            last_output: None,
+            last_actions: Vec::new(),
+            last_is_paused: false,
+            accept_thread: Some(accept_thread),
+        })
+    }
+
+    // Only publishes when something changed.
+    pub fn publish(&mut self, output: &GameCubeControllerSnapshot, actions: &[Action], is_paused: bool) {
+        if self.last_output.as_ref() == Some(output)
+        && self.last_actions == actions
+        && self.last_is_paused == is_paused {
+            return;
+        }
+        self.last_output = Some(*output);
+        self.last_actions = actions.to_vec();
+        self.last_is_paused = is_paused;
+
+        let message = OverlayMessage { controller: output, actions, is_paused };
+        if let Ok(json) = serde_json::to_string(&message) {
+            let mut latest = self.feed.latest.lock().unwrap();
+            latest.0 += 1;
+            latest.1 = json;
+            self.feed.changed.notify_all();
+        }
+    }
+}
+
+impl Drop for OverlayServer {
+    fn drop(&mut self) {
+        self.feed.is_stopping.store(true, Ordering::Release);
+        self.feed.changed.notify_all();
+        if let Some(thread) = self.accept_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn allowed_origins(address: SocketAddr, extra_origins: &[String]) -> Vec<String> {
+    let mut origins = vec![format!("http://{}", address)];
+    if address.ip().is_loopback() {
+        origins.push(format!("http://localhost:{}", address.port()));
+    }
+    origins.extend(extra_origins.iter().map(|origin| origin.trim_end_matches('/').to_string()));
+    origins
+}
+
+fn accept_connections(listener: TcpListener, feed: Arc<Feed>) {
+    while !feed.is_stopping.load(Ordering::Acquire) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let feed = feed.clone();
+                thread::spawn(move || handle_connection(stream, feed));
+            },
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+            Err(_) => thread::sleep(ACCEPT_INTERVAL),
+        }
+    }
+}
+
+// The request head is peeked rather than read so the WebSocket handshake can still read it.
+fn handle_connection(mut stream: TcpStream, feed: Arc<Feed>) {
+    if stream.set_nonblocking(false).is_err()
+    || stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT)).is_err()
+    || stream.set_read_timeout(Some(CLIENT_WAIT_TIMEOUT)).is_err() {
+        return;
+    }
+    let mut buffer = [0u8; REQUEST_PEEK_SIZE];
+    let head_length = match peek_request_head(&stream, &mut buffer) {
+        Some(head_length) => head_length,
+        None => return,
+    };
+    let request = String::from_utf8_lossy(&buffer[..head_length]).to_ascii_lowercase();
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+
+    if request.contains("upgrade: websocket") {
+        send_feed(stream, feed);
+        return;
+    }
+
+    // Closing with the request still unread would reset the connection and could cut the page off.
+    if stream.read_exact(&mut buffer[..head_length]).is_err() {
+        return;
+    }
+    if path == "/" || path == "/index.html" {
+        let _ = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            OVERLAY_PAGE.len(), OVERLAY_PAGE,
+        );
+    }
+    else {
+        let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+    }
+
+    // Let the browser close first so everything sent is sure to arrive.
+    let _ = stream.shutdown(Shutdown::Write);
+    let _ = stream.set_read_timeout(Some(CLOSE_TIMEOUT));
+    while let Ok(1..) = stream.read(&mut buffer) {}
+}
+
+// Waits for the whole request head, since it can arrive in pieces. Returns its length.
+fn peek_request_head(stream: &TcpStream, buffer: &mut [u8]) -> Option<usize> {
+    let deadline = Instant::now() + CLIENT_WAIT_TIMEOUT;
+    loop {
+        let byte_count = stream.peek(buffer).ok()?;
+        if byte_count == 0 {
+            return None;
+        }
+        if let Some(end) = buffer[..byte_count].windows(4).position(|window| window == b"\r\n\r\n") {
+            return Some(end + 4);
+        }
+        if byte_count == buffer.len() || Instant::now() >= deadline {
+            return None;
+        }
+        thread::sleep(REQUEST_PEEK_INTERVAL);
+    }
+}
+
+// tungstenite decides what the error response looks like.
+#[allow(clippy::result_large_err)]
+fn send_feed(stream: TcpStream, feed: Arc<Feed>) {
+    let check_origin = |request: &Request, response: Response| {
+        let origin = request.headers().get(ORIGIN).map(|origin| origin.to_str().unwrap_or(""));
+        match origin {
+            Some(origin) if !feed.allowed_origins.iter().any(|allowed_origin| allowed_origin.eq_ignore_ascii_case(origin)) => {
+                let mut error_response = ErrorResponse::new(Some(format!("{} may not read the overlay feed", origin)));
+                *error_response.status_mut() = StatusCode::FORBIDDEN;
+                Err(error_response)
+            },
+            _ => Ok(response),
+        }
+    };
+    let mut socket = match tungstenite::accept_hdr(stream, check_origin) {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
+    let mut last_sent = 0;
+    loop {
+        let message = {
+            let mut latest = feed.latest.lock().unwrap();
+            while latest.0 == last_sent && !feed.is_stopping.load(Ordering::Acquire) {
+                latest = feed.changed.wait_timeout(latest, CLIENT_WAIT_TIMEOUT).unwrap().0;
+            }
+            if feed.is_stopping.load(Ordering::Acquire) {
+                break;
+            }
+            last_sent = latest.0;
+            latest.1.clone()
+        };
+        if socket.send(Message::text(message)).is_err() {
+            return;
+        }
+    }
+    let _ = socket.close(None);
+}
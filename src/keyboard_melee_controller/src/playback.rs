@@ -0,0 +1,66 @@
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use digital_melee_controller::{DtmMovie, GameCubeControllerSnapshot, DTM_FRAME_RATE, parse_frame_script};
+
+// Prerecorded outputs that are sent as is, one per frame, instead of the keyboard.
+// A playback is armed until started, and goes back to armed when stopped so it
+// can be played again.
+pub struct Playback {
+    frames: Vec<GameCubeControllerSnapshot>,
+    start: Option<Instant>,
+}
+
+impl Playback {
+    pub fn new(frames: Vec<GameCubeControllerSnapshot>) -> Self {
+        Self {
+            frames,
+            start: None,
+        }
+    }
+
+    // A Dolphin movie if the file ends in .dtm, otherwise a frame script.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let is_dtm = Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("dtm"));
+        let frames = if is_dtm {
+            let mut file = File::open(path).map_err(|e| format!("Could not open {}: {}", path, e))?;
+            DtmMovie::read(&mut file).map_err(|e| format!("Could not read {}: {}", path, e))?.frames
+        } else {
+            let text = fs::read_to_string(path).map_err(|e| format!("Could not open {}: {}", path, e))?;
+            parse_frame_script(&text).map_err(|e| format!("Could not read {}: {}", path, e))?
+        };
+        Ok(Self::new(frames))
+    }
+
+    pub fn frames(&self) -> &[GameCubeControllerSnapshot] {
+        &self.frames
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn start(&mut self, time: Instant) {
+        self.start = Some(time);
+    }
+
+    pub fn stop(&mut self) {
+        self.start = None;
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.start.is_some()
+    }
+
+    // The frame to send at the given time, or None if not started or every frame has been sent.
+    pub fn frame_at(&self, time: Instant) -> Option<GameCubeControllerSnapshot> {
+        let elapsed = time.saturating_duration_since(self.start?);
+        let frame = (elapsed.as_secs_f64() * DTM_FRAME_RATE) as usize;
+        self.frames.get(frame).copied()
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames.len() as f64 / DTM_FRAME_RATE)
+    }
+}
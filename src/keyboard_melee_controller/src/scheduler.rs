@@ -0,0 +1,268 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Deserialize};
+use keyboard_input::print_message;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchedulerSettings {
+    pub tick_rate_hz: f64,
+    // The last part of every wait is spent spinning instead of sleeping, since sleeps
+    // tend to overshoot. More spinning means less jitter but more CPU use. 0 never spins.
+    pub spin_duration_us: u64,
+    pub use_real_time_priority: bool,
+    pub cpu_core: Option<usize>,
+    // 0 turns the jitter report off.
+    pub jitter_report_interval_secs: u64,
+}
+
+impl Default for SchedulerSettings {
+    fn default() -> Self {
+        Self {
+            tick_rate_hz: 1000.0,
+            spin_duration_us: 250,
+            use_real_time_priority: false,
+            cpu_core: None,
+            jitter_report_interval_secs: 0,
+        }
+    }
+}
+
+// How late ticks started compared to when they were scheduled.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct TickJitter {
+    pub tick_count: u64,
+    pub mean_lateness: Duration,
+    pub max_lateness: Duration,
+    // Ticks that started a whole tick late or more.
+    pub missed_tick_count: u64,
+}
+
+pub struct Scheduler {
+    settings: SchedulerSettings,
+    tick_duration: Duration,
+    spin_duration: Duration,
+    time_of_next_tick: Instant,
+    time_of_last_report: Instant,
+    tick_count: u64,
+    total_lateness: Duration,
+    max_lateness: Duration,
+    missed_tick_count: u64,
+    last_jitter: TickJitter,
+}
+
+impl Scheduler {
+    // Priority and pinning are applied to the calling thread.
+    pub fn new(settings: SchedulerSettings) -> Self {
+        let tick_rate_hz = if settings.tick_rate_hz > 0.0 { settings.tick_rate_hz } else { 1000.0 };
+
+        if settings.use_real_time_priority {
+            if let Err(e) = set_real_time_priority() {
+                print_message(format!("Could not raise the update thread to real time priority: {}", e));
+            }
+        }
+        if let Some(cpu_core) = settings.cpu_core {
+            if let Err(e) = pin_to_cpu_core(cpu_core) {
+                print_message(format!("Could not pin the update thread to CPU core {}: {}", cpu_core, e));
+            }
+        }
+        begin_precise_sleep();
+
+        // Spinning for a whole tick would never sleep at all, so at least half of every wait is a sleep.
+        let tick_duration = Duration::from_secs_f64(1.0 / tick_rate_hz);
+        let spin_duration = Duration::from_micros(settings.spin_duration_us).min(tick_duration / 2);
+
+        let now = Instant::now();
+        Self {
+            settings,
+            tick_duration,
+            spin_duration,
+            time_of_next_tick: now,
+            time_of_last_report: now,
+            tick_count: 0,
+            total_lateness: Duration::from_secs(0),
+            max_lateness: Duration::from_secs(0),
+            missed_tick_count: 0,
+            last_jitter: TickJitter::default(),
+        }
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        self.tick_duration
+    }
+
+    // The jitter measured over the last report interval.
+    pub fn jitter(&self) -> TickJitter {
+        self.last_jitter
+    }
+
+    pub fn wait_for_next_tick(&mut self) {
+        self.time_of_next_tick += self.tick_duration;
+
+        loop {
+            let now = Instant::now();
+            if now >= self.time_of_next_tick {
+                break;
+            }
+            let remaining = self.time_of_next_tick - now;
+            if remaining > self.spin_duration {
+                thread::sleep(remaining - self.spin_duration);
+            }
+            else {
+                std::hint::spin_loop();
+            }
+        }
+
+        let now = Instant::now();
+        let lateness = now - self.time_of_next_tick;
+        self.record_lateness(lateness);
+
+        // Rather than rushing through a burst of ticks to catch up, start over from now.
+        if lateness >= self.tick_duration {
+            self.time_of_next_tick = now;
+        }
+    }
+
+    fn record_lateness(&mut self, lateness: Duration) {
+        self.tick_count += 1;
+        self.total_lateness += lateness;
+        self.max_lateness = self.max_lateness.max(lateness);
+        if lateness >= self.tick_duration {
+            self.missed_tick_count += 1;
+        }
+
+        let report_interval = Duration::from_secs(self.settings.jitter_report_interval_secs.max(1));
+        if self.time_of_last_report.elapsed() < report_interval {
+            return;
+        }
+        self.last_jitter = TickJitter {
+            tick_count: self.tick_count,
+            mean_lateness: self.total_lateness / self.tick_count as u32,
+            max_lateness: self.max_lateness,
+            missed_tick_count: self.missed_tick_count,
+        };
+        if self.settings.jitter_report_interval_secs > 0 {
+            print_message(format!(
+                "Tick jitter: mean {} us, max {} us, {} of {} ticks missed.",
+                self.last_jitter.mean_lateness.as_micros(),
+                self.last_jitter.max_lateness.as_micros(),
+                self.last_jitter.missed_tick_count,
+                self.last_jitter.tick_count,
+            ));
+        }
+        self.time_of_last_report = Instant::now();
+        self.tick_count = 0;
+        self.total_lateness = Duration::from_secs(0);
+        self.max_lateness = Duration::from_secs(0);
+        self.missed_tick_count = 0;
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        end_precise_sleep();
+    }
+}
+
+// Windows sleeps in steps of the system timer, which is 15.6 ms unless asked otherwise.
+#[cfg(windows)]
+fn begin_precise_sleep() {
+    unsafe { winapi::um::timeapi::timeBeginPeriod(1); }
+}
+
+#[cfg(windows)]
+fn end_precise_sleep() {
+    unsafe { winapi::um::timeapi::timeEndPeriod(1); }
+}
+
+#[cfg(not(windows))]
+fn begin_precise_sleep() {}
+
+#[cfg(not(windows))]
+fn end_precise_sleep() {}
+
+#[cfg(windows)]
+fn set_real_time_priority() -> io::Result<()> {
+    use winapi::um::processthreadsapi::{GetCurrentProcess, GetCurrentThread, SetPriorityClass, SetThreadPriority};
+    use winapi::um::winbase::{HIGH_PRIORITY_CLASS, THREAD_PRIORITY_TIME_CRITICAL};
+    unsafe {
+        if SetPriorityClass(GetCurrentProcess(), HIGH_PRIORITY_CLASS) == 0
+        || SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL as i32) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn pin_to_cpu_core(cpu_core: usize) -> io::Result<()> {
+    use winapi::um::processthreadsapi::GetCurrentThread;
+    use winapi::um::winbase::SetThreadAffinityMask;
+    if cpu_core >= 8 * std::mem::size_of::<usize>() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "there is no such core"));
+    }
+    unsafe {
+        if SetThreadAffinityMask(GetCurrentThread(), 1 << cpu_core) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::os::raw::c_int;
+
+    pub const SCHED_FIFO: c_int = 1;
+    pub const CPU_SET_WORDS: usize = 16;
+
+    #[repr(C)]
+    pub struct SchedParam {
+        pub sched_priority: c_int,
+    }
+
+    extern "C" {
+        pub fn sched_get_priority_max(policy: c_int) -> c_int;
+        pub fn sched_setscheduler(pid: c_int, policy: c_int, param: *const SchedParam) -> c_int;
+        pub fn sched_setaffinity(pid: c_int, cpu_set_size: usize, cpu_set: *const u64) -> c_int;
+    }
+}
+
+// Needs CAP_SYS_NICE or an rtprio limit, otherwise this fails with a permission error.
+#[cfg(target_os = "linux")]
+fn set_real_time_priority() -> io::Result<()> {
+    unsafe {
+        let param = linux::SchedParam { sched_priority: linux::sched_get_priority_max(linux::SCHED_FIFO) };
+        if linux::sched_setscheduler(0, linux::SCHED_FIFO, &param) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn pin_to_cpu_core(cpu_core: usize) -> io::Result<()> {
+    let mut cpu_set = [0u64; linux::CPU_SET_WORDS];
+    match cpu_set.get_mut(cpu_core / 64) {
+        Some(word) => *word |= 1 << (cpu_core % 64),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "there is no such core")),
+    }
+    unsafe {
+        if linux::sched_setaffinity(0, std::mem::size_of_val(&cpu_set), cpu_set.as_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn set_real_time_priority() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "not supported on this platform"))
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn pin_to_cpu_core(_cpu_core: usize) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "not supported on this platform"))
+}
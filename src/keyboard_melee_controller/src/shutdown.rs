@@ -0,0 +1,58 @@
+use std::cell::Cell;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use keyboard_input::print_message;
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+static NEUTRAL_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static CONTAINS_PANICS: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn request_shutdown() {
+    SHUTDOWN_REQUESTED.store(true, Ordering::Release);
+}
+
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::Acquire)
+}
+
+// Once set, only neutral is sent to the vJoy device from then on.
+pub fn neutral_requested() -> bool {
+    NEUTRAL_REQUESTED.load(Ordering::Acquire)
+}
+
+// For optional work on its own thread, like following a game. A panic there stops
+// only that work instead of the controller. Returns false if it panicked.
+pub(crate) fn run_contained<F: FnOnce()>(work: F) -> bool {
+    CONTAINS_PANICS.with(|contains_panics| contains_panics.set(true));
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(work));
+    CONTAINS_PANICS.with(|contains_panics| contains_panics.set(false));
+    result.is_ok()
+}
+
+// Ctrl+C, closing the console and termination signals ask the main loop to stop.
+// A panic on any thread unblocks the keyboard right away, since the thread that
+// panicked might never get to shut down cleanly. The vJoy device can only be
+// reached from the main thread, so the panic just asks for neutral, which the
+// next update or the unwinding controller sends. That is best effort: a panic
+// that aborts, or a main thread that is stuck, leaves the last output held.
+pub fn install_shutdown_handlers() {
+    if let Err(e) = ctrlc::set_handler(request_shutdown) {
+        println!("Could not install the Ctrl+C handler: {}", e);
+    }
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if CONTAINS_PANICS.try_with(|contains_panics| contains_panics.get()).unwrap_or(false) {
+            print_message(info.to_string());
+            return;
+        }
+        keyboard_input::unblock_all_keys();
+        NEUTRAL_REQUESTED.store(true, Ordering::Release);
+        request_shutdown();
+        default_hook(info);
+    }));
+}
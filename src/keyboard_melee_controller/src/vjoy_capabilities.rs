@@ -0,0 +1,125 @@
+use vjoy_device::{VJoyDevice, VJoyAxis, VJoyError};
+
+use crate::{KeyboardMeleeControllerConfig, DPadOutput, is_d_pad_button, print_message};
+
+fn vjoy_config_axis_name(axis: VJoyAxis) -> &'static str {
+    match axis {
+        VJoyAxis::X => "X",
+        VJoyAxis::Y => "Y",
+        VJoyAxis::Z => "Z",
+        VJoyAxis::XRotation => "Rx",
+        VJoyAxis::YRotation => "Ry",
+        VJoyAxis::ZRotation => "Rz",
+        VJoyAxis::Slider0 => "Slider",
+        VJoyAxis::Slider1 => "Dial/Slider2",
+        VJoyAxis::Throttle => "Throttle",
+        VJoyAxis::Rudder => "Rudder",
+        VJoyAxis::Aileron => "Aileron",
+        VJoyAxis::Wheel => "Wheel",
+        VJoyAxis::VX => "VX",
+        VJoyAxis::VY => "VY",
+        VJoyAxis::VZ => "VZ",
+        VJoyAxis::VBRX => "VBRX",
+        VJoyAxis::VBRY => "VBRY",
+        VJoyAxis::VBRZ => "VBRZ",
+    }
+}
+
+// Returns a description of every binding the device will silently drop.
+fn find_missing_capabilities(
+    config: &KeyboardMeleeControllerConfig,
+    device: &VJoyDevice,
+) -> Result<Vec<String>, VJoyError> {
+    let mut problems = Vec::new();
+
+    let button_count = device.button_count()?;
+    for (button, button_id) in &config.vjoy_button_binds {
+        if config.vjoy_d_pad_output != DPadOutput::Buttons && is_d_pad_button(*button) {
+            continue;
+        }
+        if *button_id == 0 || *button_id > 128 {
+            problems.push(format!("{:?} is bound to button {}, but vJoy buttons range from 1 to 128.", button, button_id));
+        }
+        else if *button_id > button_count {
+            problems.push(format!("{:?} is bound to button {}, but the device only has {} buttons.", button, button_id, button_count));
+        }
+    }
+
+    let axes = config.vjoy_axis_binds.iter().map(|(input, axis)| (format!("{:?} axis", input), *axis));
+    let sliders = config.vjoy_slider_binds.iter().map(|(input, axis)| (format!("{:?} slider", input), *axis));
+    for (input_name, axis) in axes.chain(sliders) {
+        match device.axis_exists(axis)? {
+            Some(true) => (),
+            Some(false) => problems.push(format!(
+                "{} is bound to {}, which is not enabled on the device.",
+                input_name, vjoy_config_axis_name(axis),
+            )),
+            None => problems.push(format!(
+                "{} is bound to {}, which vJoy can't report on. Make sure the device has it.",
+                input_name, vjoy_config_axis_name(axis),
+            )),
+        }
+    }
+
+    let pov_id = config.vjoy_d_pad_pov_id;
+    match config.vjoy_d_pad_output {
+        DPadOutput::Buttons => (),
+        DPadOutput::DiscretePov => {
+            let pov_count = device.discrete_pov_count()?;
+            if pov_id == 0 || pov_id > pov_count {
+                problems.push(format!("The D-pad is output to discrete POV hat {}, but the device has {}.", pov_id, pov_count));
+            }
+        },
+        DPadOutput::ContinuousPov => {
+            let pov_count = device.continuous_pov_count()?;
+            if pov_id == 0 || pov_id > pov_count {
+                problems.push(format!("The D-pad is output to continuous POV hat {}, but the device has {}.", pov_id, pov_count));
+            }
+        },
+    }
+
+    Ok(problems)
+}
+
+fn suggested_configuration(config: &KeyboardMeleeControllerConfig) -> String {
+    let button_count = config.vjoy_button_binds.iter()
+        .filter(|(button, _)| config.vjoy_d_pad_output == DPadOutput::Buttons || !is_d_pad_button(**button))
+        .map(|(_, button_id)| *button_id)
+        .filter(|button_id| *button_id <= 128)
+        .max()
+        .unwrap_or(0);
+
+    let mut axis_names: Vec<&str> = config.vjoy_axis_binds.values()
+        .chain(config.vjoy_slider_binds.values())
+        .map(|axis| vjoy_config_axis_name(*axis))
+        .collect();
+    axis_names.sort_unstable();
+    axis_names.dedup();
+
+    let povs = match config.vjoy_d_pad_output {
+        DPadOutput::Buttons => "none".to_string(),
+        DPadOutput::DiscretePov => format!("{} discrete", config.vjoy_d_pad_pov_id),
+        DPadOutput::ContinuousPov => format!("{} continuous", config.vjoy_d_pad_pov_id),
+    };
+
+    format!(
+        "Suggested vJoy configuration for device {}:\n  Axes: {}\n  Buttons: {}\n  POV hats: {}",
+        config.vjoy_device_id, axis_names.join(", "), button_count, povs,
+    )
+}
+
+pub fn report_missing_vjoy_capabilities(config: &KeyboardMeleeControllerConfig, device: &VJoyDevice) {
+    match find_missing_capabilities(config, device) {
+        Ok(problems) => {
+            if problems.is_empty() {
+                return;
+            }
+            print_message(format!("vJoy device {} does not match config.json, some inputs will be dropped:", config.vjoy_device_id));
+            for problem in &problems {
+                print_message(format!("  {}", problem));
+            }
+            print_message(suggested_configuration(config));
+        },
+        Err(e) => print_message(format!("Could not check the capabilities of vJoy device {}: {}", config.vjoy_device_id, e)),
+    }
+}
@@ -1,25 +1,156 @@
-use std::thread;
-use std::time::{Instant, Duration};
-use keyboard_melee_controller::KeyboardMeleeController;
+mod dashboard;
+
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::Instant;
+
+use digital_melee_controller::{DtmMovie, SlippiReplay, DTM_FRAME_RATE, SLIPPI_FIRST_FRAME, compare_inputs};
+use keyboard_melee_controller::{KeyboardMeleeController, Playback, Scheduler};
+
+use crate::dashboard::Dashboard;
 
 fn main() {
-    let mut controller = KeyboardMeleeController::new();
+    let args: Vec<String> = env::args().collect();
+
+    // Works offline, so the controller is never started.
+    if let Some(replay_path) = arg_value(&args, "--compare-slp") {
+        compare_slp(&args, &replay_path);
+        return;
+    }
+
+    keyboard_melee_controller::install_shutdown_handlers();
+
+    let mut controller = match KeyboardMeleeController::new() {
+        Ok(controller) => controller,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        },
+    };
+
+    // Records latency while running and prints it on exit.
+    let show_latency_report = env::args().any(|arg| arg == "--latency-report");
+    if show_latency_report {
+        controller.enable_latency_recording();
+        println!("Recording input latency, the report is printed on exit.");
+    }
 
-    let update_rate = Duration::from_millis(1);
+    // Records every output sent and writes it as a Dolphin movie on exit. The movie
+    // plays from boot, so the game has to be started right as recording starts.
+    let dtm_path = arg_value(&args, "--record-dtm");
+    if let Some(dtm_path) = &dtm_path {
+        controller.enable_output_recording();
+        println!("Recording outputs, {} is written on exit.", dtm_path);
+        println!("The movie plays from boot, so start the game in Dolphin now for it to play back the same.");
+    }
 
-    //let mut time_of_last_loop = Instant::now();
-    let mut time_of_next_loop = Instant::now();
-    loop {
-        //println!("{:?}", Instant::now() - time_of_last_loop);
-        //time_of_last_loop = Instant::now();
+    // Sends the outputs from a Dolphin movie or frame script instead of the keyboard.
+    if let Some(playback_path) = arg_value(&args, "--playback") {
+        match Playback::load(&playback_path) {
+            Ok(playback) => {
+                println!("Loaded {} frames ({:.2} s) from {}.", playback.frame_count(), playback.duration().as_secs_f64(), playback_path);
+                controller.arm_playback(playback);
+                if controller.has_playback_trigger() {
+                    println!("Start it with playback_hotkey or the playback control command.");
+                }
+                else if let Err(e) = controller.start_playback() {
+                    println!("{}", e);
+                }
+            },
+            Err(e) => println!("{}", e),
+        }
+    }
 
-        time_of_next_loop += update_rate;
+    let mut dashboard = None;
+    if env::args().any(|arg| arg == "--dashboard") {
+        match Dashboard::start() {
+            Ok(started_dashboard) => dashboard = Some(started_dashboard),
+            Err(e) => println!("Could not start the dashboard: {}", e),
+        }
+    }
 
+    let mut scheduler = Scheduler::new(controller.scheduler_settings());
+    while !keyboard_melee_controller::shutdown_requested() {
         controller.update();
+        if let Some(dashboard) = &mut dashboard {
+            dashboard.publish(&controller);
+        }
+        scheduler.wait_for_next_tick();
+    }
+    if let Some(recording) = controller.output_recording_mut() {
+        recording.finish(Instant::now());
+    }
+
+    // The terminal has to be restored before anything else is printed.
+    drop(dashboard);
+
+    controller.shutdown();
+    println!("Shut down, the controller was left at neutral.");
+
+    if show_latency_report {
+        if let Some(recorder) = controller.latency_recorder() {
+            recorder.print();
+        }
+    }
 
-        let now = Instant::now();
-        if time_of_next_loop > now {
-            thread::sleep(time_of_next_loop - now);
+    if let Some(dtm_path) = &dtm_path {
+        if let Some(recording) = controller.output_recording() {
+            write_dtm(dtm_path, DtmMovie::new(recording.frames(DTM_FRAME_RATE)));
         }
     }
 }
+
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+fn write_dtm(path: &str, mut movie: DtmMovie) {
+    movie.author = String::from("keyboard_melee");
+    let result = File::create(path).and_then(|file| movie.write(&mut BufWriter::new(file)));
+    match result {
+        Ok(()) => println!("Wrote {} frames to {}.", movie.frames.len(), path),
+        Err(e) => println!("Could not write {}: {}", path, e),
+    }
+}
+
+// --compare-slp <replay.slp> <trace.dtm or frame script> [--port 1-4]
+fn compare_slp(args: &[String], replay_path: &str) {
+    let trace_path = match args.iter().position(|arg| arg == "--compare-slp").and_then(|i| args.get(i + 2)) {
+        Some(trace_path) => trace_path,
+        None => {
+            println!("Usage: --compare-slp <replay.slp> <trace.dtm or frame script> [--port 1-4]");
+            return;
+        },
+    };
+    let port = match arg_value(args, "--port").map(|port| port.parse::<u8>()) {
+        None => 1,
+        Some(Ok(port)) if (1..=4).contains(&port) => port,
+        Some(_) => {
+            println!("The port has to be 1 to 4.");
+            return;
+        },
+    };
+
+    let replay = match File::open(replay_path).and_then(|mut file| SlippiReplay::read(&mut file, port - 1)) {
+        Ok(replay) => replay,
+        Err(e) => {
+            println!("Could not read {}: {}", replay_path, e);
+            return;
+        },
+    };
+    let trace = match Playback::load(trace_path) {
+        Ok(trace) => trace,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        },
+    };
+
+    let comparison = compare_inputs(trace.frames(), &replay.frames);
+    println!("Output frame 0 lines up with game frame {} on port {}.", comparison.offset + SLIPPI_FIRST_FRAME as i64, port);
+    comparison.print(50);
+}
@@ -1,13 +1,21 @@
 extern crate libloading as lib;
 
+mod vjoy_error;
+
 use std::os::raw::{
     c_uchar,
     c_long,
     c_uint,
+    c_int,
 };
+use std::time::{Instant, Duration};
 
 use serde::{Serialize, Deserialize};
 
+pub use crate::vjoy_error::VJoyError;
+
+const POV_NEUTRAL: c_uint = 0xFFFFFFFF;
+
 #[allow(non_snake_case)]
 #[repr(C)]
 #[derive(Default, Copy, Clone)]
@@ -54,57 +62,254 @@ pub enum VJoyAxis {
     ZRotation,
     Slider0,
     Slider1,
+    Throttle,
+    Rudder,
+    Aileron,
+    Wheel,
+    VX,
+    VY,
+    VZ,
+    VBRX,
+    VBRY,
+    VBRZ,
+}
+
+impl VJoyAxis {
+    fn hid_usage(self) -> Option<c_uint> {
+        match self {
+            VJoyAxis::X => Some(0x30),
+            VJoyAxis::Y => Some(0x31),
+            VJoyAxis::Z => Some(0x32),
+            VJoyAxis::XRotation => Some(0x33),
+            VJoyAxis::YRotation => Some(0x34),
+            VJoyAxis::ZRotation => Some(0x35),
+            VJoyAxis::Slider0 => Some(0x36),
+            VJoyAxis::Slider1 => Some(0x37),
+            VJoyAxis::Wheel => Some(0x38),
+            VJoyAxis::Aileron => Some(0xB0),
+            VJoyAxis::Rudder => Some(0xBA),
+            VJoyAxis::Throttle => Some(0xBB),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VJoyDeviceStatus {
+    Owned,
+    Free,
+    Busy,
+    Missing,
+    Unknown,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VJoyPovDirection {
+    North,
+    East,
+    South,
+    West,
+}
+
+const ACQUIRE_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct VJoyDevice {
     device_id: u32,
     vjoy: lib::Library,
     state: JoystickPositionV2,
+    is_acquired: bool,
+    last_acquire_attempt: Instant,
 }
 
 impl VJoyDevice {
-    pub fn new(device_id: u32, path: &str) -> Self {
-        let output = Self {
+    pub fn new(device_id: u32, path: &str) -> Result<Self, VJoyError> {
+        let vjoy = lib::Library::new(path).map_err(|error| VJoyError::LibraryLoad {
+            path: path.to_string(),
+            error,
+        })?;
+        let mut output = Self {
             device_id,
-            vjoy: lib::Library::new(path).expect("Could not load vJoyInterface.dll."),
-            state: JoystickPositionV2::default(),
+            vjoy,
+            is_acquired: false,
+            last_acquire_attempt: Instant::now(),
+            state: JoystickPositionV2 {
+                bHats: POV_NEUTRAL,
+                bHatsEx1: POV_NEUTRAL,
+                bHatsEx2: POV_NEUTRAL,
+                bHatsEx3: POV_NEUTRAL,
+                ..Default::default()
+            },
+        };
+        if !output.vjoy_is_enabled()? {
+            return Err(VJoyError::Disabled);
+        }
+        output.acquire()?;
+        Ok(output)
+    }
+
+    pub fn device_id(&self) -> u32 { self.device_id }
+    pub fn is_acquired(&self) -> bool { self.is_acquired }
+
+    pub fn status(&self) -> Result<VJoyDeviceStatus, VJoyError> {
+        unsafe {
+            let func = self.function::<unsafe extern "C" fn(c_uint) -> c_int>("GetVJDStatus")?;
+            Ok(match func(self.device_id) {
+                0 => VJoyDeviceStatus::Owned,
+                1 => VJoyDeviceStatus::Free,
+                2 => VJoyDeviceStatus::Busy,
+                3 => VJoyDeviceStatus::Missing,
+                _ => VJoyDeviceStatus::Unknown,
+            })
+        }
+    }
+
+    pub fn button_count(&self) -> Result<u32, VJoyError> {
+        unsafe {
+            let func = self.function::<unsafe extern "C" fn(c_uint) -> c_int>("GetVJDButtonNumber")?;
+            Ok(func(self.device_id).max(0) as u32)
+        }
+    }
+
+    pub fn discrete_pov_count(&self) -> Result<u32, VJoyError> {
+        unsafe {
+            let func = self.function::<unsafe extern "C" fn(c_uint) -> c_int>("GetVJDDiscPovNumber")?;
+            Ok(func(self.device_id).max(0) as u32)
+        }
+    }
+
+    pub fn continuous_pov_count(&self) -> Result<u32, VJoyError> {
+        unsafe {
+            let func = self.function::<unsafe extern "C" fn(c_uint) -> c_int>("GetVJDContPovNumber")?;
+            Ok(func(self.device_id).max(0) as u32)
+        }
+    }
+
+    // vJoy has no way to query the axes it doesn't have HID usages for.
+    pub fn axis_exists(&self, axis: VJoyAxis) -> Result<Option<bool>, VJoyError> {
+        let usage = match axis.hid_usage() {
+            Some(usage) => usage,
+            None => return Ok(None),
         };
-        if output.v_joy_is_enabled() {
-            output.acquire_vjoy_device();
+        unsafe {
+            let func = self.function::<unsafe extern "C" fn(c_uint, c_uint) -> c_int>("GetVJDAxisExist")?;
+            Ok(Some(func(self.device_id, usage) != 0))
         }
-        output
+    }
+
+    pub fn acquire(&mut self) -> Result<(), VJoyError> {
+        self.last_acquire_attempt = Instant::now();
+        match self.status()? {
+            VJoyDeviceStatus::Owned => {
+                self.is_acquired = true;
+                return Ok(());
+            },
+            VJoyDeviceStatus::Free => (),
+            status => return Err(VJoyError::DeviceUnavailable { device_id: self.device_id, status }),
+        }
+        unsafe {
+            let func = self.function::<unsafe extern "C" fn(c_uint) -> c_int>("AcquireVJD")?;
+            if func(self.device_id) == 0 {
+                return Err(VJoyError::AcquireFailed(self.device_id));
+            }
+        }
+        self.is_acquired = true;
+        Ok(())
     }
 
     pub fn set_button(&mut self, button_id: u32, state: bool) {
-        let bit_index: u32 = button_id - 1;
+        let buttons = match button_id {
+            1..=32 => &mut self.state.lButtons,
+            33..=64 => &mut self.state.lButtonsEx1,
+            65..=96 => &mut self.state.lButtonsEx2,
+            97..=128 => &mut self.state.lButtonsEx3,
+            _ => return,
+        };
+        let bit_index: u32 = (button_id - 1) % 32;
         if state {
-            self.state.lButtons = self.state.lButtons | (1 << bit_index);
+            *buttons |= 1 << bit_index;
         }
         else {
-            self.state.lButtons = self.state.lButtons & !(1 << bit_index);
+            *buttons &= !(1 << bit_index);
         }
     }
 
-    pub fn set_axis(&mut self, axis: VJoyAxis, value: f64) {
-        match axis {
-            VJoyAxis::X => self.state.wAxisX = Self::get_scaled_axis_value(value),
-            VJoyAxis::Y => self.state.wAxisY = Self::get_scaled_axis_value(value),
-            VJoyAxis::Z => self.state.wAxisZ = Self::get_scaled_axis_value(value),
-            VJoyAxis::XRotation => self.state.wAxisXRot = Self::get_scaled_axis_value(value),
-            VJoyAxis::YRotation => self.state.wAxisYRot = Self::get_scaled_axis_value(value),
-            VJoyAxis::ZRotation => self.state.wAxisZRot = Self::get_scaled_axis_value(value),
-            VJoyAxis::Slider0 => self.state.wSlider = Self::get_scaled_slider_value(value),
-            VJoyAxis::Slider1 => self.state.wSlider = Self::get_scaled_slider_value(value),
+    // Discrete POV hats share bHats, four bits each.
+    pub fn set_discrete_pov(&mut self, pov_id: u32, direction: Option<VJoyPovDirection>) {
+        if !(1..=4).contains(&pov_id) {
+            return;
         }
+        let value: c_uint = match direction {
+            Some(VJoyPovDirection::North) => 0,
+            Some(VJoyPovDirection::East) => 1,
+            Some(VJoyPovDirection::South) => 2,
+            Some(VJoyPovDirection::West) => 3,
+            None => 0xF,
+        };
+        let shift = (pov_id - 1) * 4;
+        self.state.bHats = (self.state.bHats & !(0xF << shift)) | (value << shift);
+    }
+
+    // Continuous POV hats are in hundredths of a degree clockwise from north.
+    pub fn set_continuous_pov(&mut self, pov_id: u32, angle: Option<u32>) {
+        let hat = match pov_id {
+            1 => &mut self.state.bHats,
+            2 => &mut self.state.bHatsEx1,
+            3 => &mut self.state.bHatsEx2,
+            4 => &mut self.state.bHatsEx3,
+            _ => return,
+        };
+        *hat = match angle {
+            Some(angle) => angle % 36000,
+            None => POV_NEUTRAL,
+        };
+    }
+
+    pub fn set_axis(&mut self, axis: VJoyAxis, value: f64) {
+        *self.axis_value_mut(axis) = Self::get_scaled_axis_value(value);
+    }
+
+    pub fn set_slider(&mut self, axis: VJoyAxis, value: f64) {
+        *self.axis_value_mut(axis) = Self::get_scaled_slider_value(value);
     }
 
-    pub fn send_inputs(&self) {
+    // Tries to reacquire the device if it was lost, at most once per retry interval.
+    pub fn send_inputs(&mut self) -> Result<(), VJoyError> {
+        if !self.is_acquired {
+            if Instant::now() - self.last_acquire_attempt < ACQUIRE_RETRY_INTERVAL {
+                return Err(VJoyError::AcquireFailed(self.device_id));
+            }
+            self.acquire()?;
+        }
         unsafe {
-            let func = self.vjoy.get::<lib::Symbol<unsafe extern fn(u32, JoystickPositionV2) -> u32>>(b"UpdateVJD")
-                .expect(&format!("Could not update vJoy device {}.", self.device_id));
-            if func(self.device_id, self.state) == 0 {
-                panic!("Could not update vJoy device {}.", self.device_id);
-            };
+            let func = self.function::<unsafe extern "C" fn(c_uint, *const JoystickPositionV2) -> c_int>("UpdateVJD")?;
+            if func(self.device_id, &self.state) == 0 {
+                self.is_acquired = false;
+                return Err(VJoyError::UpdateFailed(self.device_id));
+            }
+        }
+        Ok(())
+    }
+
+    fn axis_value_mut(&mut self, axis: VJoyAxis) -> &mut c_long {
+        match axis {
+            VJoyAxis::X => &mut self.state.wAxisX,
+            VJoyAxis::Y => &mut self.state.wAxisY,
+            VJoyAxis::Z => &mut self.state.wAxisZ,
+            VJoyAxis::XRotation => &mut self.state.wAxisXRot,
+            VJoyAxis::YRotation => &mut self.state.wAxisYRot,
+            VJoyAxis::ZRotation => &mut self.state.wAxisZRot,
+            VJoyAxis::Slider0 => &mut self.state.wSlider,
+            VJoyAxis::Slider1 => &mut self.state.wDial,
+            VJoyAxis::Throttle => &mut self.state.wThrottle,
+            VJoyAxis::Rudder => &mut self.state.wRudder,
+            VJoyAxis::Aileron => &mut self.state.wAileron,
+            VJoyAxis::Wheel => &mut self.state.wWheel,
+            VJoyAxis::VX => &mut self.state.wAxisVX,
+            VJoyAxis::VY => &mut self.state.wAxisVY,
+            VJoyAxis::VZ => &mut self.state.wAxisVZ,
+            VJoyAxis::VBRX => &mut self.state.wAxisVBRX,
+            VJoyAxis::VBRY => &mut self.state.wAxisVBRY,
+            VJoyAxis::VBRZ => &mut self.state.wAxisVBRZ,
         }
     }
 
@@ -117,38 +322,34 @@ impl VJoyDevice {
         return (value * (0x8000 as f64)) as c_long;
     }
 
-    fn v_joy_is_enabled(&self) -> bool {
-        unsafe {
-            if let Ok(func) = self.vjoy.get::<lib::Symbol<unsafe extern fn() -> u32>>(b"vJoyEnabled") {
-                if func() == 1 { true } else { false }
-            }
-            else {
-                false
-            }
-        }
+    unsafe fn function<T>(&self, name: &'static str) -> Result<lib::Symbol<'_, T>, VJoyError> {
+        let symbol_name = format!("{}\0", name);
+        self.vjoy.get::<T>(symbol_name.as_bytes()).map_err(|_| VJoyError::MissingFunction(name))
     }
 
-    fn acquire_vjoy_device(&self) {
+    fn vjoy_is_enabled(&self) -> Result<bool, VJoyError> {
         unsafe {
-            let func = self.vjoy.get::<lib::Symbol<unsafe extern fn(u32) -> u32>>(b"AcquireVJD")
-                .expect(&format!("Could not acquire vJoy device {}.", self.device_id));
-            if func(self.device_id) == 0 {
-                panic!("Could not acquire vJoy device {}.", self.device_id);
-            };
+            let func = self.function::<unsafe extern "C" fn() -> c_int>("vJoyEnabled")?;
+            Ok(func() != 0)
         }
     }
 
-    fn free_vjoy_device(&self) {
+    // Lets other programs take the device. Sending inputs acquires it again.
+    pub fn relinquish(&mut self) {
+        if !self.is_acquired {
+            return;
+        }
         unsafe {
-            let func = self.vjoy.get::<lib::Symbol<unsafe extern fn(u32)>>(b"RelinquishVJD")
-                .expect(&format!("Could not free vJoy device {}.", self.device_id));
-            func(self.device_id);
+            if let Ok(func) = self.function::<unsafe extern "C" fn(c_uint)>("RelinquishVJD") {
+                func(self.device_id);
+            }
         }
+        self.is_acquired = false;
     }
 }
 
 impl Drop for VJoyDevice {
     fn drop(&mut self) {
-        self.free_vjoy_device();
+        self.relinquish();
     }
 }
@@ -0,0 +1,46 @@
+use std::fmt;
+
+use crate::VJoyDeviceStatus;
+
+#[derive(Debug)]
+pub enum VJoyError {
+    LibraryLoad { path: String, error: libloading::Error },
+    MissingFunction(&'static str),
+    Disabled,
+    DeviceUnavailable { device_id: u32, status: VJoyDeviceStatus },
+    AcquireFailed(u32),
+    UpdateFailed(u32),
+}
+
+impl fmt::Display for VJoyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VJoyError::LibraryLoad { path, error } => write!(
+                f, "Could not load vJoyInterface.dll from \"{}\" ({}). Is vJoy installed and is vjoy_dll_path in config.json correct?",
+                path, error,
+            ),
+            VJoyError::MissingFunction(name) => write!(
+                f, "vJoyInterface.dll does not export {}. Your vJoy version may be too old.",
+                name,
+            ),
+            VJoyError::Disabled => write!(
+                f, "vJoy is installed but disabled. Enable it in the vJoy configuration tool.",
+            ),
+            VJoyError::DeviceUnavailable { device_id, status } => match status {
+                VJoyDeviceStatus::Busy => write!(
+                    f, "vJoy device {} is owned by another program. Close it or choose a different vjoy_device_id.",
+                    device_id,
+                ),
+                VJoyDeviceStatus::Missing => write!(
+                    f, "vJoy device {} does not exist. Add it in the vJoy configuration tool.",
+                    device_id,
+                ),
+                _ => write!(f, "vJoy device {} is unavailable ({:?}).", device_id, status),
+            },
+            VJoyError::AcquireFailed(device_id) => write!(f, "Could not acquire vJoy device {}.", device_id),
+            VJoyError::UpdateFailed(device_id) => write!(f, "Could not update vJoy device {}.", device_id),
+        }
+    }
+}
+
+impl std::error::Error for VJoyError {}